@@ -1,8 +1,10 @@
 //! ECS components.
 
 use bevy_core::Timer;
+use bevy_ecs::entity::{EntityMap, MapEntities, MapEntitiesError};
 use bevy_ecs::prelude::*;
-use bevy_reflect::{Reflect, TypeRegistry};
+use bevy_ecs::reflect::ReflectMapEntities;
+use bevy_reflect::{Reflect, ReflectDeserialize, TypeRegistry};
 use bracket_lib::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -19,37 +21,59 @@ pub fn register_component_types(type_registry: &mut TypeRegistry) {
 
     register_types!(
         Position,
+        OtherLevelPosition,
         EntityName,
         BlocksTile,
         CombatStats,
+        Attributes,
         Renderable,
         Viewshed,
         Player,
         Mob,
+        Movement,
         WantsToMelee,
         CanSufferDamage,
+        StatusEffects,
+        Energy,
         ParticleLifetime,
         Item,
         WantsToPickupItem,
         Owned,
         ConsumableEffects,
+        Ranged,
         WantsToUseItem,
         WantsToDropItem,
         EquipmentSlot,
         Equippable,
         Equipped,
         ItemStats,
+        // Value types used in component fields.
+        bevy_utils::HashSet<Position>,
+        Option<i32>,
+        Option<String>,
+        Option<Position>,
+        Option<StatusEffect>,
     );
 }
 
 /// A component that gives an entity a position.
 #[derive(Debug, Reflect, Component, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
-#[reflect_value(Component, Serialize)]
+#[reflect_value(Component, Serialize, Deserialize)]
 pub struct Position {
     pub x: u32,
     pub y: u32,
 }
 
+/// A component that replaces [`Position`] for the entities of the levels that the player is not
+/// on. The entities are frozen until the player comes back to their level.
+#[derive(Debug, Reflect, Component, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct OtherLevelPosition {
+    pub x: u32,
+    pub y: u32,
+    pub depth: i32,
+}
+
 /// A component that gives an entity a name.
 #[derive(Debug, Reflect, Component, Default)]
 #[reflect(Component)]
@@ -71,9 +95,75 @@ pub struct CombatStats {
     pub power: i32,
 }
 
+impl CombatStats {
+    /// Returns the stats of an entity with the given attributes, where `self` are the stats of an
+    /// entity with average attributes.
+    pub fn with_attributes(self, attributes: &Attributes) -> Self {
+        Self {
+            hp: self.hp + attributes.max_hp_bonus(),
+            max_hp: self.max_hp + attributes.max_hp_bonus(),
+            ..self
+        }
+    }
+}
+
+/// Average value of an attribute. Attributes above it give bonuses, attributes below it give
+/// penalties.
+pub const AVERAGE_ATTRIBUTE: i32 = 10;
+
+/// Returns the bonus given by the value of an attribute: `+1` every two points above average.
+pub fn attribute_bonus(value: i32) -> i32 {
+    (value - AVERAGE_ATTRIBUTE).div_euclid(2)
+}
+
+/// A component containing the attributes of a character. The combat values of the character are
+/// derived from them.
+///
+/// Entities without attributes have average ones.
+#[derive(Debug, Reflect, Component, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[reflect(Component)]
+#[serde(default)]
+pub struct Attributes {
+    /// Gives a damage bonus.
+    pub might: i32,
+    /// Gives more max hp.
+    pub fitness: i32,
+    /// Gives a to-hit bonus.
+    pub quickness: i32,
+    pub intelligence: i32,
+}
+
+impl Default for Attributes {
+    fn default() -> Self {
+        Self {
+            might: AVERAGE_ATTRIBUTE,
+            fitness: AVERAGE_ATTRIBUTE,
+            quickness: AVERAGE_ATTRIBUTE,
+            intelligence: AVERAGE_ATTRIBUTE,
+        }
+    }
+}
+
+impl Attributes {
+    /// Max hp added to the base max hp of the character.
+    pub fn max_hp_bonus(&self) -> i32 {
+        attribute_bonus(self.fitness) * 5
+    }
+
+    /// Bonus added to the to-hit roll of the melee attacks of the character.
+    pub fn to_hit_bonus(&self) -> i32 {
+        attribute_bonus(self.quickness)
+    }
+
+    /// Damage added to the melee attacks of the character.
+    pub fn damage_bonus(&self) -> i32 {
+        attribute_bonus(self.might)
+    }
+}
+
 /// A component that contains the data needed to render a tile.
 #[derive(Debug, Reflect, Component, Default, Serialize, Deserialize, Clone, Copy)]
-#[reflect_value(Component, Serialize)]
+#[reflect_value(Component, Serialize, Deserialize)]
 pub struct Renderable {
     #[serde(deserialize_with = "crate::deserialize::u16_from_cp437")]
     pub glyph: u16,
@@ -125,6 +215,8 @@ pub struct PlayerBundle {
     pub renderable: Renderable,
     pub viewshed: Viewshed,
     pub combat_stats: CombatStats,
+    pub attributes: Attributes,
+    pub energy: Energy,
     pub can_suffer_damage: CanSufferDamage,
 }
 
@@ -133,6 +225,41 @@ pub struct PlayerBundle {
 #[reflect(Component)]
 pub struct Mob;
 
+/// How a mob moves when it is not next to the player. Mobs always attack the player when they are
+/// next to them.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MovementMode {
+    /// Never moves, like a guard.
+    Static,
+    /// Moves randomly and ignores the player.
+    Random,
+    /// Moves randomly until it sees the player, then chases them.
+    Wandering,
+    /// Waits until it sees the player, then chases them.
+    #[default]
+    Chase,
+}
+
+/// A component that gives a mob a [`MovementMode`].
+#[derive(Debug, Reflect, Component, Default, Serialize, Deserialize, Clone, Copy)]
+#[reflect_value(Component, Serialize, Deserialize)]
+pub struct Movement {
+    pub mode: MovementMode,
+    /// Last position where the mob saw the player. Mobs that chase the player walk there after
+    /// losing sight of them.
+    pub last_known_player_position: Option<Position>,
+}
+
+impl Movement {
+    pub fn new(mode: MovementMode) -> Self {
+        Self {
+            mode,
+            last_known_player_position: None,
+        }
+    }
+}
+
 #[derive(Bundle)]
 pub struct MobBundle {
     pub mob: Mob,
@@ -140,8 +267,11 @@ pub struct MobBundle {
     pub position: Position,
     pub renderable: Renderable,
     pub viewshed: Viewshed,
+    pub movement: Movement,
     pub blocks_tile: BlocksTile,
     pub combat_stats: CombatStats,
+    pub attributes: Attributes,
+    pub energy: Energy,
     pub can_suffer_damage: CanSufferDamage,
 }
 
@@ -163,6 +293,122 @@ pub struct WantsToMelee {
 #[reflect(Component)]
 pub struct CanSufferDamage {
     pub amount: Vec<i32>,
+    /// Name of the last entity that damaged this entity. Used to count kills and as the cause of
+    /// death.
+    pub last_attacker: Option<String>,
+}
+
+/// Kinds of [`StatusEffect`]s.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StatusEffectKind {
+    /// Moves in random directions instead of the intended ones.
+    Confusion,
+    /// Suffers `damage` every turn.
+    Poison { damage: i32 },
+    /// Heals `heal` hp every turn.
+    Regeneration { heal: i32 },
+    /// Acts 50% faster.
+    Haste,
+    /// Acts at half speed.
+    Slow,
+}
+
+impl StatusEffectKind {
+    /// Returns the name displayed to the player.
+    pub fn name(&self) -> &'static str {
+        match self {
+            StatusEffectKind::Confusion => "Confusion",
+            StatusEffectKind::Poison { .. } => "Poison",
+            StatusEffectKind::Regeneration { .. } => "Regeneration",
+            StatusEffectKind::Haste => "Haste",
+            StatusEffectKind::Slow => "Slow",
+        }
+    }
+}
+
+/// A [`StatusEffectKind`] that lasts for a number of turns.
+#[derive(Debug, Reflect, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[reflect_value(Serialize, Deserialize)]
+pub struct StatusEffect {
+    #[serde(flatten)]
+    pub kind: StatusEffectKind,
+    pub turns: i32,
+}
+
+/// A component with the status effects of an entity. The effects are ticked once per turn by
+/// [`status_effect_system`](crate::systems::status_effects::status_effect_system).
+#[derive(Debug, Reflect, Component, Default, Serialize, Deserialize, Clone)]
+#[reflect_value(Component, Serialize, Deserialize)]
+pub struct StatusEffects {
+    pub effects: Vec<StatusEffect>,
+}
+
+impl StatusEffects {
+    /// Adds an effect. If the entity already has an effect of the same kind, it is replaced if the
+    /// new one lasts longer.
+    pub fn add(&mut self, effect: StatusEffect) {
+        let same_kind = self.effects.iter_mut().find(|current| {
+            std::mem::discriminant(&current.kind) == std::mem::discriminant(&effect.kind)
+        });
+        match same_kind {
+            Some(current) if current.turns < effect.turns => *current = effect,
+            Some(_) => {}
+            None => self.effects.push(effect),
+        }
+    }
+
+    pub fn has(&self, kind: StatusEffectKind) -> bool {
+        self.effects.iter().any(|effect| effect.kind == kind)
+    }
+
+    /// Returns the [speed](Energy::speed) of the entity under haste and slow.
+    pub fn speed(&self, speed: i32) -> i32 {
+        let mut speed = speed;
+        if self.has(StatusEffectKind::Haste) {
+            speed = speed * 3 / 2;
+        }
+        if self.has(StatusEffectKind::Slow) {
+            speed /= 2;
+        }
+        speed
+    }
+}
+
+/// Energy spent by most actions: moving, attacking, picking up or using an item.
+pub const ACTION_COST: i32 = 100;
+/// Energy spent by equipping an item.
+pub const EQUIP_COST: i32 = 200;
+/// Energy spent by dropping an item.
+pub const DROP_COST: i32 = 50;
+/// Speed of the player, and of the mobs that do not have one in the raws.
+pub const NORMAL_SPEED: i32 = 100;
+
+/// A component for entities that take turns.
+///
+/// Every turn, entities gain energy equal to their speed. They can act as long as they have at
+/// least [`ACTION_COST`] energy, and every action spends energy depending on how long it takes.
+/// Fast entities act several times per turn and slow ones skip turns.
+#[derive(Debug, Reflect, Component, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct Energy {
+    pub energy: i32,
+    /// Energy gained per turn.
+    pub speed: i32,
+}
+
+impl Energy {
+    /// Creates the energy of an entity that can act right away.
+    pub fn new(speed: i32) -> Self {
+        Self {
+            energy: ACTION_COST,
+            speed,
+        }
+    }
+
+    pub fn can_act(&self) -> bool {
+        self.energy >= ACTION_COST
+    }
 }
 
 #[derive(Debug, Reflect, Component, Default)]
@@ -191,12 +437,20 @@ pub struct WantsToPickupItem {
 }
 
 /// An entity that is owned by another entity (e.g. an item that is in the player's backpack).
-#[derive(Debug, Reflect, Component)]
-#[reflect(Component)]
+#[derive(Debug, Reflect, Component, Serialize, Deserialize, Clone, Copy)]
+#[reflect_value(Component, MapEntities, Serialize, Deserialize)]
 pub struct Owned {
+    #[serde(deserialize_with = "crate::deserialize::entity_from_id")]
     pub owner: Entity,
 }
 
+impl MapEntities for Owned {
+    fn map_entities(&mut self, entity_map: &EntityMap) -> Result<(), MapEntitiesError> {
+        self.owner = entity_map.get(self.owner)?;
+        Ok(())
+    }
+}
+
 // TODO: this is a hack to get around the fact that `Reflect` needs `impl FromWorld`.
 impl FromWorld for Owned {
     fn from_world(_world: &mut World) -> Self {
@@ -211,6 +465,21 @@ impl FromWorld for Owned {
 #[reflect(Component)]
 pub struct ConsumableEffects {
     pub heal: Option<i32>,
+    pub damage: Option<i32>,
+    /// Radius of the area affected around the target.
+    pub area_of_effect: Option<i32>,
+    /// Status effect given to the targets.
+    pub status_effect: Option<StatusEffect>,
+    /// Moves the targets to a random tile of the level.
+    #[serde(default)]
+    pub teleport: bool,
+}
+
+/// An item that is used on a tile chosen by the player, at most `range` tiles away.
+#[derive(Debug, Reflect, Component, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct Ranged {
+    pub range: i32,
 }
 
 /// Not reflected as component.
@@ -218,6 +487,8 @@ pub struct ConsumableEffects {
 pub struct WantsToUseItem {
     /// `item` must have an [`Item`] component.
     pub item: Entity,
+    /// The tile that the item is used on, for [`Ranged`] items.
+    pub target: Option<Position>,
 }
 
 /// Not reflected as component.
@@ -229,7 +500,7 @@ pub struct WantsToDropItem {
 
 #[derive(Debug, Reflect, Component, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
-#[reflect_value(Serialize)]
+#[reflect_value(Serialize, Deserialize)]
 pub enum EquipmentSlot {
     Melee,
     Shield,
@@ -250,14 +521,22 @@ impl FromWorld for Equippable {
     }
 }
 
-#[derive(Debug, Reflect, Component)]
-#[reflect(Component)]
+#[derive(Debug, Reflect, Component, Serialize, Deserialize, Clone, Copy)]
+#[reflect_value(Component, MapEntities, Serialize, Deserialize)]
 pub struct Equipped {
+    #[serde(deserialize_with = "crate::deserialize::entity_from_id")]
     pub by: Entity,
     /// This field should match the `slot` field on [`Equippable`].
     pub slot: EquipmentSlot,
 }
 
+impl MapEntities for Equipped {
+    fn map_entities(&mut self, entity_map: &EntityMap) -> Result<(), MapEntitiesError> {
+        self.by = entity_map.get(self.by)?;
+        Ok(())
+    }
+}
+
 // TODO: this is a hack to get around the fact that `Reflect` needs `impl FromWorld`.
 impl FromWorld for Equipped {
     fn from_world(_world: &mut World) -> Self {
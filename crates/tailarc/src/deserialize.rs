@@ -2,11 +2,13 @@
 
 use std::fmt;
 
+use bevy_ecs::entity::Entity;
 use bracket_lib::prelude::{to_cp437, RGB};
-use serde::{de, Deserializer};
+use serde::{de, Deserialize, Deserializer};
 
+/// Deserializes a glyph from a char. A raw `u16` glyph (as written in save files) is also accepted.
 pub fn u16_from_cp437<'de, D: Deserializer<'de>>(d: D) -> Result<u16, D::Error> {
-    d.deserialize_char(CP437Visitor)
+    d.deserialize_any(CP437Visitor)
 }
 
 struct CP437Visitor;
@@ -42,10 +44,19 @@ impl<'de> de::Visitor<'de> for CP437Visitor {
             Ok(c)
         }
     }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        u16::try_from(v).map_err(|_| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+    }
 }
 
+/// Deserializes a color from a hex string. A `{ r, g, b }` map (as written in save files) is also
+/// accepted.
 pub fn rgb_from_hex<'de, D: Deserializer<'de>>(d: D) -> Result<RGB, D::Error> {
-    d.deserialize_str(RGBVisitor)
+    d.deserialize_any(RGBVisitor)
 }
 
 struct RGBVisitor;
@@ -63,4 +74,19 @@ impl<'de> de::Visitor<'de> for RGBVisitor {
     {
         RGB::from_hex(v).map_err(|_| E::custom("invalid hex color"))
     }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::MapAccess<'de>,
+    {
+        RGB::deserialize(de::value::MapAccessDeserializer::new(map))
+    }
+}
+
+/// Deserializes an entity from its id, as written in save files.
+///
+/// The `Deserialize` implementation of [`Entity`] only accepts `u32` values, which self-describing
+/// formats such as JSON never produce.
+pub fn entity_from_id<'de, D: Deserializer<'de>>(d: D) -> Result<Entity, D::Error> {
+    u32::deserialize(d).map(Entity::from_raw)
 }
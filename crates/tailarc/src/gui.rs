@@ -2,10 +2,14 @@ use bevy_ecs::prelude::*;
 use bracket_lib::prelude::*;
 
 use crate::components::{
-    CombatStats, EntityName, Item, Owned, Player, WantsToDropItem, WantsToUseItem,
+    attribute_bonus, Attributes, CombatStats, EntityName, Equipped, Item, ItemStats, Owned, Player,
+    Position, Ranged, StatusEffects, Viewshed, WantsToDropItem, WantsToUseItem,
 };
 use crate::gamelog::GameLog;
 use crate::map::Map;
+use crate::morgue::Morgue;
+use crate::render::screen_position;
+use crate::rng::GameRng;
 use crate::{CONSOLE_HEIGHT, CONSOLE_WIDTH};
 
 /// Render in game UI.
@@ -13,7 +17,8 @@ pub fn render_ui_system(
     mut ctx: ResMut<BTerm>,
     map: Res<Map>,
     game_log: Res<GameLog>,
-    player: Query<&CombatStats, With<Player>>,
+    rng: Res<GameRng>,
+    player: Query<(&CombatStats, Option<&StatusEffects>), With<Player>>,
 ) {
     // Draw ui box.
     ctx.draw_box_double(
@@ -36,7 +41,7 @@ pub fn render_ui_system(
     );
 
     // Draw player health.
-    let stats = player.single();
+    let (stats, status_effects) = player.single();
 
     let health = format!(" HP: {} / {} ", stats.hp, stats.max_hp);
     ctx.print_color(
@@ -56,6 +61,33 @@ pub fn render_ui_system(
         RGB::named(BLACK),
     );
 
+    // Draw seed so that the game can be shared.
+    let seed = format!(" Seed: {} ", rng.seed());
+    ctx.print_color(
+        2,
+        CONSOLE_HEIGHT - 1,
+        RGB::named(GRAY),
+        RGB::named(BLACK),
+        &seed,
+    );
+
+    // Draw the active status effects of the player.
+    if let Some(status_effects) = status_effects {
+        let effects: Vec<_> = status_effects
+            .effects
+            .iter()
+            .map(|effect| format!("{} ({})", effect.kind.name(), effect.turns))
+            .collect();
+        let effects = format!(" {} ", effects.join(", "));
+        ctx.print_color(
+            (CONSOLE_WIDTH - 2).saturating_sub(effects.len() as u32),
+            CONSOLE_HEIGHT - 1,
+            RGB::named(ORANGE),
+            RGB::named(BLACK),
+            &effects,
+        );
+    }
+
     // Draw game log.
     let mut y = CONSOLE_HEIGHT - 6;
     for log in game_log.entries.lock().unwrap().iter().rev() {
@@ -72,20 +104,23 @@ pub enum ItemMenuResult {
     Cancel,
     NoResponse,
     Selected,
+    /// A [`Ranged`] item was selected. Its target must be chosen before using it.
+    Targeting,
 }
 
 pub fn render_inventory(
     mut commands: Commands,
     mut ctx: ResMut<BTerm>,
     mut item_menu_result: ResMut<ItemMenuResult>,
-    player: Query<Entity, With<Player>>,
-    items: Query<(Entity, &EntityName, &Owned), With<Item>>,
+    mut targeting: ResMut<Targeting>,
+    player: Query<(Entity, &Position), With<Player>>,
+    items: Query<(Entity, &EntityName, &Owned, Option<&Ranged>), With<Item>>,
 ) {
-    let player_entity = player.single();
+    let (player_entity, &player_pos) = player.single();
 
     let inventory: Vec<_> = items
         .iter()
-        .filter(|(_, _, i)| i.owner == player_entity)
+        .filter(|(_, _, i, _)| i.owner == player_entity)
         .collect();
     let count = inventory.len();
 
@@ -113,7 +148,7 @@ pub fn render_inventory(
         "ESCAPE to cancel",
     );
 
-    for (j, (_, name, _)) in inventory.iter().enumerate() {
+    for (j, (_, name, _, _)) in inventory.iter().enumerate() {
         ctx.set(17, y, RGB::named(WHITE), RGB::named(BLACK), to_cp437('('));
         ctx.set(
             18,
@@ -135,18 +170,119 @@ pub fn render_inventory(
             if selection == -1 || selection >= count as i32 {
                 ItemMenuResult::NoResponse
             } else {
-                // Add a WantsToUseItem component to the player with the selected item.
-                let (item, _, _) = inventory[selection as usize];
-                commands
-                    .entity(player_entity)
-                    .insert(WantsToUseItem { item });
-                ItemMenuResult::Selected
+                let (item, _, _, ranged) = inventory[selection as usize];
+                if let Some(ranged) = ranged {
+                    *targeting = Targeting {
+                        item: Some(item),
+                        range: ranged.range,
+                        cursor: player_pos,
+                    };
+                    ItemMenuResult::Targeting
+                } else {
+                    // Add a WantsToUseItem component to the player with the selected item.
+                    commands
+                        .entity(player_entity)
+                        .insert(WantsToUseItem { item, target: None });
+                    ItemMenuResult::Selected
+                }
             }
         }
         _ => ItemMenuResult::NoResponse,
     };
 }
 
+/// A resource describing the choice of the target of a [`Ranged`] item.
+#[derive(Debug, Default)]
+pub struct Targeting {
+    /// The item being used.
+    pub item: Option<Entity>,
+    pub range: i32,
+    /// The tile that is currently selected.
+    pub cursor: Position,
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum TargetingResult {
+    Cancel,
+    NoResponse,
+    Selected,
+}
+
+/// Render the selection of the target of a [`Ranged`] item.
+///
+/// The player can target any visible tile in range of the item.
+pub fn render_targeting(
+    mut commands: Commands,
+    mut ctx: ResMut<BTerm>,
+    mut targeting: ResMut<Targeting>,
+    mut targeting_result: ResMut<TargetingResult>,
+    player: Query<(Entity, &Position, &Viewshed), With<Player>>,
+) {
+    let (player_entity, &player_pos, viewshed) = player.single();
+    let range = targeting.range as f32;
+    let in_range = |pos: &Position| {
+        DistanceAlg::Pythagoras.distance2d(
+            Point::new(player_pos.x, player_pos.y),
+            Point::new(pos.x, pos.y),
+        ) <= range
+    };
+
+    // Highlight the tiles that can be targeted.
+    for pos in viewshed.visible_tiles.iter().filter(|pos| in_range(pos)) {
+        let (x, y) = screen_position(player_pos, *pos);
+        ctx.set_bg(x, y, RGB::named(BLUE));
+    }
+    let valid_target =
+        viewshed.visible_tiles.contains(&targeting.cursor) && in_range(&targeting.cursor);
+    let (x, y) = screen_position(player_pos, targeting.cursor);
+    let cursor_color = if valid_target {
+        RGB::named(CYAN)
+    } else {
+        RGB::named(RED)
+    };
+    ctx.set_bg(x, y, cursor_color);
+    ctx.print_color(
+        2,
+        0,
+        RGB::named(YELLOW),
+        RGB::named(BLACK),
+        " Select target: ENTER to confirm, ESCAPE to cancel ",
+    );
+
+    let (delta_x, delta_y) = match ctx.key {
+        Some(VirtualKeyCode::Left) | Some(VirtualKeyCode::H) => (-1, 0),
+        Some(VirtualKeyCode::Right) | Some(VirtualKeyCode::L) => (1, 0),
+        Some(VirtualKeyCode::Up) | Some(VirtualKeyCode::K) => (0, -1),
+        Some(VirtualKeyCode::Down) | Some(VirtualKeyCode::J) => (0, 1),
+        Some(VirtualKeyCode::Y) => (-1, -1),
+        Some(VirtualKeyCode::U) => (1, -1),
+        Some(VirtualKeyCode::B) => (-1, 1),
+        Some(VirtualKeyCode::N) => (1, 1),
+        _ => (0, 0),
+    };
+    // The cursor stays on the visible tiles.
+    let cursor = Position {
+        x: (targeting.cursor.x as i32 + delta_x).max(0) as u32,
+        y: (targeting.cursor.y as i32 + delta_y).max(0) as u32,
+    };
+    if viewshed.visible_tiles.contains(&cursor) {
+        targeting.cursor = cursor;
+    }
+
+    *targeting_result = match ctx.key {
+        Some(VirtualKeyCode::Escape) => TargetingResult::Cancel,
+        Some(VirtualKeyCode::Return) if valid_target => {
+            let item = targeting.item.expect("no item is being targeted");
+            commands.entity(player_entity).insert(WantsToUseItem {
+                item,
+                target: Some(targeting.cursor),
+            });
+            TargetingResult::Selected
+        }
+        _ => TargetingResult::NoResponse,
+    };
+}
+
 /// Render drop item menu.
 #[derive(PartialEq, Copy, Clone)]
 pub enum DropItemResult {
@@ -221,3 +357,137 @@ pub fn render_drop_item_menu(
         _ => DropItemResult::NoResponse,
     };
 }
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum CharacterSheetResult {
+    NoResponse,
+    Close,
+}
+
+/// Render the character sheet of the player.
+pub fn render_character_sheet(
+    mut ctx: ResMut<BTerm>,
+    mut character_sheet_result: ResMut<CharacterSheetResult>,
+    player: Query<(Entity, &EntityName, &CombatStats, Option<&Attributes>), With<Player>>,
+    equipped: Query<(&Equipped, &ItemStats)>,
+) {
+    let (player_entity, name, stats, attributes) = player.single();
+    let attributes = attributes.copied().unwrap_or_default();
+    let (power_bonus, defense_bonus) = equipped
+        .iter()
+        .filter(|(equipped, _)| equipped.by == player_entity)
+        .fold((0, 0), |(power, defense), (_, item_stats)| {
+            (power + item_stats.power, defense + item_stats.defense)
+        });
+
+    let lines = [
+        format!(
+            "Might:        {:>3} ({:+})",
+            attributes.might,
+            attribute_bonus(attributes.might)
+        ),
+        format!(
+            "Fitness:      {:>3} ({:+})",
+            attributes.fitness,
+            attribute_bonus(attributes.fitness)
+        ),
+        format!(
+            "Quickness:    {:>3} ({:+})",
+            attributes.quickness,
+            attribute_bonus(attributes.quickness)
+        ),
+        format!(
+            "Intelligence: {:>3} ({:+})",
+            attributes.intelligence,
+            attribute_bonus(attributes.intelligence)
+        ),
+        String::new(),
+        format!("HP:      {} / {}", stats.hp, stats.max_hp),
+        format!(
+            "Power:   {} ({:+} might, {:+} equipment)",
+            stats.power + attributes.damage_bonus() + power_bonus,
+            attributes.damage_bonus(),
+            power_bonus
+        ),
+        format!(
+            "Defense: {} ({:+} equipment)",
+            stats.defense + defense_bonus,
+            defense_bonus
+        ),
+        format!("To-hit:  {:+}", attributes.to_hit_bonus()),
+    ];
+
+    let y = 15;
+    ctx.draw_box(
+        15,
+        y - 2,
+        50,
+        (lines.len() + 3) as i32,
+        RGB::named(WHITE),
+        RGB::named(BLACK),
+    );
+    ctx.print_color(18, y - 2, RGB::named(YELLOW), RGB::named(BLACK), &name.name);
+    ctx.print_color(
+        18,
+        y + lines.len() as i32 + 1,
+        RGB::named(YELLOW),
+        RGB::named(BLACK),
+        "ESCAPE to close",
+    );
+    for (i, line) in lines.iter().enumerate() {
+        ctx.print(18, y + i as i32, line);
+    }
+
+    if ctx.key == Some(VirtualKeyCode::Escape) {
+        *character_sheet_result = CharacterSheetResult::Close;
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum GameOverResult {
+    NoSelection,
+    QuitToMenu,
+}
+
+/// Render the death screen.
+pub fn render_game_over(
+    mut ctx: ResMut<BTerm>,
+    mut game_over_result: ResMut<GameOverResult>,
+    morgue: Res<Morgue>,
+) {
+    ctx.cls();
+
+    ctx.print_color_centered(15, RGB::named(RED), RGB::named(BLACK), "You are dead!");
+
+    let mut y = 18;
+    for line in &morgue.summary {
+        ctx.print_color_centered(y, RGB::named(WHITE), RGB::named(BLACK), line);
+        y += 1;
+    }
+
+    y += 1;
+    match &morgue.path {
+        Some(path) => {
+            ctx.print_color_centered(y, RGB::named(GRAY), RGB::named(BLACK), "Morgue file:");
+            ctx.print_color_centered(y + 1, RGB::named(GRAY), RGB::named(BLACK), path.display());
+        }
+        None => ctx.print_color_centered(
+            y,
+            RGB::named(GRAY),
+            RGB::named(BLACK),
+            "The morgue file could not be written.",
+        ),
+    }
+
+    ctx.print_color_centered(
+        y + 4,
+        RGB::named(MAGENTA),
+        RGB::named(BLACK),
+        "Press ENTER to return to the main menu",
+    );
+
+    *game_over_result = match ctx.key {
+        Some(VirtualKeyCode::Return) => GameOverResult::QuitToMenu,
+        _ => GameOverResult::NoSelection,
+    };
+}
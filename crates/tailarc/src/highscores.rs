@@ -0,0 +1,208 @@
+//! Hall of fame of the past games.
+//!
+//! The high score table is stored next to the save files and is updated every time a game ends.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use bevy_ecs::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::components::{CanSufferDamage, EntityName, Player};
+use crate::map::Map;
+use crate::morgue::KillCounts;
+use crate::save::{data_dir, timestamp_now};
+use crate::TurnCounter;
+
+/// Maximum number of entries kept in the high score table.
+pub const MAX_HIGH_SCORES: usize = 10;
+
+/// Returns the score of a game.
+///
+/// Going deeper is worth the most, then killing monsters. Every turn survived is worth a point.
+pub fn score(depth: i32, kills: u32, turns: u32) -> u32 {
+    depth.max(0) as u32 * 1000 + kills * 50 + turns
+}
+
+/// An entry in the high score table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HighScore {
+    pub character_name: String,
+    pub score: u32,
+    pub depth: i32,
+    pub kills: u32,
+    pub turns: u32,
+    /// How the game ended (e.g. `Killed by Goblin`).
+    pub cause: String,
+    /// Time at which the game ended in seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// A resource containing the high score table, sorted by descending score.
+///
+/// This is only refreshed by calling [`HighScores::read`].
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HighScores {
+    pub entries: Vec<HighScore>,
+}
+
+impl HighScores {
+    /// Returns the path of the high score table.
+    pub fn path() -> PathBuf {
+        [data_dir().as_path(), Path::new("highscores.json")]
+            .into_iter()
+            .collect()
+    }
+
+    /// Reads the high score table from disk. Returns an empty table if it does not exist or could
+    /// not be read.
+    pub fn read() -> Self {
+        let path = Self::path();
+        if !path.exists() {
+            return Self::default();
+        }
+        match fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|data| serde_json::from_str(&data).map_err(|err| err.to_string()))
+        {
+            Ok(high_scores) => high_scores,
+            Err(err) => {
+                tracing::warn!("could not read high scores: {}", err);
+                Self::default()
+            }
+        }
+    }
+
+    /// Writes the high score table to disk.
+    ///
+    /// Like save files, the table is written to a temporary file first so that it is not corrupted
+    /// if the game crashes while writing.
+    pub fn write(&self) -> io::Result<()> {
+        fs::create_dir_all(data_dir())?;
+        let path = Self::path();
+        let tmp_path = path.with_extension("json.tmp");
+        let data = serde_json::to_string(self).expect("could not serialize high scores into JSON");
+        fs::write(&tmp_path, data)?;
+        fs::rename(&tmp_path, &path)
+    }
+
+    /// Inserts an entry into the table, dropping the lowest entries if the table is full.
+    ///
+    /// Returns the rank of the entry (starting at `0`) or `None` if its score is too low.
+    pub fn insert(&mut self, entry: HighScore) -> Option<usize> {
+        // Entries with the same score are ranked by age.
+        let rank = self
+            .entries
+            .iter()
+            .position(|other| other.score < entry.score)
+            .unwrap_or(self.entries.len());
+        if rank >= MAX_HIGH_SCORES {
+            return None;
+        }
+        self.entries.insert(rank, entry);
+        self.entries.truncate(MAX_HIGH_SCORES);
+        Some(rank)
+    }
+}
+
+/// Records the score of the player that just died into the high score table.
+pub fn record_high_score_system(
+    map: Res<Map>,
+    kill_counts: Res<KillCounts>,
+    turn_counter: Res<TurnCounter>,
+    player: Query<(&EntityName, &CanSufferDamage), With<Player>>,
+) {
+    let (name, can_suffer_damage) = player.single();
+    let kills = kill_counts.total();
+    let entry = HighScore {
+        character_name: name.name.clone(),
+        score: score(map.depth, kills, turn_counter.turn),
+        depth: map.depth,
+        kills,
+        turns: turn_counter.turn,
+        cause: match &can_suffer_damage.last_attacker {
+            Some(attacker) => format!("Killed by {}", attacker),
+            None => "Died".to_string(),
+        },
+        timestamp: timestamp_now(),
+    };
+
+    let mut high_scores = HighScores::read();
+    if high_scores.insert(entry).is_some() {
+        if let Err(err) = high_scores.write() {
+            tracing::error!("could not write high scores: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(character_name: &str, score: u32) -> HighScore {
+        HighScore {
+            character_name: character_name.to_string(),
+            score,
+            depth: 1,
+            kills: 0,
+            turns: 0,
+            cause: "Died".to_string(),
+            timestamp: 0,
+        }
+    }
+
+    fn names(high_scores: &HighScores) -> Vec<&str> {
+        high_scores
+            .entries
+            .iter()
+            .map(|entry| entry.character_name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn score_depth_then_kills_then_turns() {
+        assert_eq!(score(1, 0, 0), 1000);
+        assert_eq!(score(3, 2, 42), 3142);
+        assert_eq!(score(-1, 1, 5), 55);
+    }
+
+    #[test]
+    fn insert_by_descending_score() {
+        let mut high_scores = HighScores::default();
+        assert_eq!(high_scores.insert(entry("a", 100)), Some(0));
+        assert_eq!(high_scores.insert(entry("b", 300)), Some(0));
+        assert_eq!(high_scores.insert(entry("c", 200)), Some(1));
+        assert_eq!(names(&high_scores), vec!["b", "c", "a"]);
+    }
+
+    #[test]
+    fn insert_ties_after_older_entries() {
+        let mut high_scores = HighScores::default();
+        high_scores.insert(entry("old", 100));
+        assert_eq!(high_scores.insert(entry("new", 100)), Some(1));
+        assert_eq!(names(&high_scores), vec!["old", "new"]);
+    }
+
+    #[test]
+    fn insert_into_full_table() {
+        let mut high_scores = HighScores::default();
+        for score in 1..=MAX_HIGH_SCORES as u32 {
+            high_scores.insert(entry("a", score * 10));
+        }
+
+        // Too low, or tied with the last entry.
+        assert_eq!(high_scores.insert(entry("b", 5)), None);
+        assert_eq!(high_scores.insert(entry("b", 10)), None);
+        assert_eq!(high_scores.entries.len(), MAX_HIGH_SCORES);
+
+        // The lowest entry is dropped.
+        assert_eq!(
+            high_scores.insert(entry("c", 15)),
+            Some(MAX_HIGH_SCORES - 1)
+        );
+        assert_eq!(high_scores.entries.len(), MAX_HIGH_SCORES);
+        assert_eq!(high_scores.entries.last().unwrap().character_name, "c");
+        assert!(high_scores.entries.iter().all(|entry| entry.score != 10));
+    }
+}
@@ -7,12 +7,17 @@ pub mod components;
 pub mod deserialize;
 pub mod gamelog;
 pub mod gui;
+pub mod highscores;
 pub mod map;
 pub mod map_builders;
+pub mod morgue;
 pub mod raws;
 pub mod render;
+pub mod rng;
+pub mod save;
 pub mod systems;
 
+use std::path::PathBuf;
 use std::sync::Mutex;
 
 use bevy_app::CoreStage;
@@ -55,26 +60,51 @@ pub const DEBUG_GOD_MODE: bool =
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum RunState {
     MainMenu,
+    ShowSeedEntry,
+    NewGame,
     ShowInventory,
     ShowDropItem,
+    ShowCharacterSheet,
+    ShowTargeting,
+    ShowSaveSlots,
+    ShowLoadSlots,
+    ShowHighScores,
     SaveGame,
+    SaveAndQuit,
+    LoadGame,
     AwaitingInput,
     Player,
     Monster,
+    NextLevel,
+    PreviousLevel,
+    GameOver,
 }
 
 impl RunState {
     #[track_caller]
     pub fn advance_state(state: &mut ResMut<State<Self>>) {
         let next = match state.current() {
-            RunState::MainMenu => None,      // Main menu stays in main menu.
+            RunState::MainMenu => None, // Main menu stays in main menu.
+            RunState::ShowSeedEntry => None,
+            RunState::NewGame => None,
             RunState::ShowInventory => None, // Inventory does not close by itself!
             RunState::ShowDropItem => None,
+            RunState::ShowCharacterSheet => None,
+            RunState::ShowTargeting => None,
+            RunState::ShowSaveSlots => None,
+            RunState::ShowLoadSlots => None,
+            RunState::ShowHighScores => None,
             RunState::SaveGame => None,
+            RunState::SaveAndQuit => None,
+            RunState::LoadGame => None,
             // Game loop.
             RunState::AwaitingInput => Some(RunState::Player),
             RunState::Player => Some(RunState::Monster),
             RunState::Monster => Some(RunState::AwaitingInput),
+            RunState::NextLevel => None,
+            RunState::PreviousLevel => None,
+            // Game over screen is closed by the player.
+            RunState::GameOver => None,
         };
         if let Some(next) = next {
             let _ = state.set(next);
@@ -82,44 +112,185 @@ impl RunState {
     }
 }
 
+/// A resource counting the number of turns since the start of the game.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TurnCounter {
+    pub turn: u32,
+}
+
 /// Advances the [`RunState`] to the next state (for the next tick).
 pub fn next_turn_state_system(
     mut state: ResMut<State<RunState>>,
-    main_menu_result: Res<render::MainMenuResult>,
+    mut turn_counter: ResMut<TurnCounter>,
+    mut save_slots: ResMut<save::SaveSlots>,
+    mut active_slot: ResMut<save::ActiveSlot>,
+    // Some parameters are grouped in tuples because systems take at most 16 parameters.
+    (mut run, mut game_rng): (ResMut<save::Run>, ResMut<rng::GameRng>),
+    mut main_menu_result: ResMut<render::MainMenuResult>,
+    mut seed_entry_result: ResMut<render::SeedEntryResult>,
+    mut save_slot_menu_result: ResMut<render::SaveSlotMenuResult>,
     item_menu_result: Res<gui::ItemMenuResult>,
     drop_item_result: Res<gui::DropItemResult>,
+    mut character_sheet_result: ResMut<gui::CharacterSheetResult>,
+    mut targeting_result: ResMut<gui::TargetingResult>,
+    mut game_over_result: ResMut<gui::GameOverResult>,
+    (mut high_scores, mut high_scores_result): (
+        ResMut<highscores::HighScores>,
+        ResMut<render::HighScoresResult>,
+    ),
+    player: Query<&components::Energy, With<components::Player>>,
 ) {
+    // The player waits for the monsters to play until they have enough energy.
+    let player_can_act = player
+        .get_single()
+        .map_or(true, components::Energy::can_act);
+
     if *state.current() == RunState::MainMenu {
         if let render::MainMenuResult::Selected { selected } = *main_menu_result {
+            // Reset the selection so that it is not handled again when we come back to the menu.
+            *main_menu_result = render::MainMenuResult::NoSelection { selected };
             match selected {
-                render::MainMenuSelection::NewGame => state.set(RunState::AwaitingInput).unwrap(),
-                render::MainMenuSelection::LoadGame => todo!("load state"),
+                render::MainMenuSelection::NewGame
+                | render::MainMenuSelection::NewPermadeathGame => {
+                    *run = save::Run::new(selected == render::MainMenuSelection::NewPermadeathGame);
+                    *game_rng = rng::GameRng::from_entropy();
+                    state.set(RunState::NewGame).unwrap();
+                }
+                render::MainMenuSelection::EnterSeed => {
+                    *run = save::Run::new(false);
+                    *seed_entry_result = render::SeedEntryResult::NoSelection {
+                        text: String::new(),
+                    };
+                    state.set(RunState::ShowSeedEntry).unwrap();
+                }
+                render::MainMenuSelection::DailyChallenge => {
+                    *run = save::Run::new(false);
+                    *game_rng = rng::GameRng::new(rng::daily_seed(save::timestamp_now()));
+                    state.set(RunState::NewGame).unwrap();
+                }
+                render::MainMenuSelection::LoadGame => {
+                    *save_slots = save::SaveSlots::read();
+                    state.set(RunState::ShowLoadSlots).unwrap();
+                }
+                render::MainMenuSelection::HallOfFame => {
+                    *high_scores = highscores::HighScores::read();
+                    state.set(RunState::ShowHighScores).unwrap();
+                }
                 render::MainMenuSelection::Quit => std::process::exit(0),
             }
         }
+    } else if *state.current() == RunState::ShowSeedEntry {
+        match *seed_entry_result {
+            render::SeedEntryResult::NoSelection { .. } => {}
+            render::SeedEntryResult::Selected { seed } => {
+                *game_rng = rng::GameRng::new(seed);
+                state.set(RunState::NewGame).unwrap();
+            }
+            render::SeedEntryResult::Cancel => state.set(RunState::MainMenu).unwrap(),
+        }
+    } else if matches!(
+        state.current(),
+        RunState::ShowLoadSlots | RunState::ShowSaveSlots
+    ) {
+        let loading = *state.current() == RunState::ShowLoadSlots;
+        match *save_slot_menu_result {
+            render::SaveSlotMenuResult::NoSelection { .. } => {}
+            // A permadeath game can only be saved when quitting.
+            render::SaveSlotMenuResult::Selected { .. } if !loading && run.permadeath => {}
+            render::SaveSlotMenuResult::Selected { selected } => {
+                active_slot.0 = Some(selected);
+                if loading {
+                    state.set(RunState::LoadGame).unwrap();
+                } else {
+                    state.set(RunState::SaveGame).unwrap();
+                }
+            }
+            render::SaveSlotMenuResult::SaveAndQuit { selected } => {
+                active_slot.0 = Some(selected);
+                state.set(RunState::SaveAndQuit).unwrap();
+            }
+            render::SaveSlotMenuResult::Delete { selected } => {
+                save::delete_slot(selected);
+                *save_slots = save::SaveSlots::read();
+                if active_slot.0 == Some(selected) {
+                    // The current game is not saved anymore.
+                    active_slot.0 = None;
+                }
+            }
+            render::SaveSlotMenuResult::Cancel => {
+                if loading {
+                    state.set(RunState::MainMenu).unwrap();
+                } else {
+                    state.set(RunState::AwaitingInput).unwrap();
+                }
+            }
+        }
+        // Reset the selection so that it is not handled again.
+        *save_slot_menu_result = render::SaveSlotMenuResult::NoSelection {
+            selected: save_slot_menu_result.selected(),
+        };
     } else if *state.current() == RunState::ShowInventory {
         match *item_menu_result {
             gui::ItemMenuResult::Cancel => state.set(RunState::AwaitingInput).unwrap(),
             gui::ItemMenuResult::NoResponse => {}
             gui::ItemMenuResult::Selected => state.set(RunState::Player).unwrap(), /* Using an item takes up a turn. */
+            gui::ItemMenuResult::Targeting => state.set(RunState::ShowTargeting).unwrap(),
+        }
+    } else if *state.current() == RunState::ShowTargeting {
+        match *targeting_result {
+            gui::TargetingResult::Cancel => state.set(RunState::AwaitingInput).unwrap(),
+            gui::TargetingResult::NoResponse => {}
+            gui::TargetingResult::Selected => state.set(RunState::Player).unwrap(),
         }
+        // Reset the result so that it is not handled again when targeting the next time.
+        *targeting_result = gui::TargetingResult::NoResponse;
     } else if *state.current() == RunState::ShowDropItem {
         match *drop_item_result {
             gui::DropItemResult::Cancel => state.set(RunState::AwaitingInput).unwrap(),
             gui::DropItemResult::NoResponse => {}
             gui::DropItemResult::Selected => state.set(RunState::Player).unwrap(), /* Using an item takes up a turn. */
         }
+    } else if *state.current() == RunState::ShowCharacterSheet {
+        if *character_sheet_result == gui::CharacterSheetResult::Close {
+            *character_sheet_result = gui::CharacterSheetResult::NoResponse;
+            state.set(RunState::AwaitingInput).unwrap();
+        }
+    } else if *state.current() == RunState::ShowHighScores {
+        if *high_scores_result == render::HighScoresResult::Close {
+            *high_scores_result = render::HighScoresResult::NoResponse;
+            state.set(RunState::MainMenu).unwrap();
+        }
+    } else if *state.current() == RunState::GameOver {
+        if *game_over_result == gui::GameOverResult::QuitToMenu {
+            *game_over_result = gui::GameOverResult::NoSelection;
+            state.set(RunState::MainMenu).unwrap();
+        }
+    } else if *state.current() == RunState::Player && player_can_act {
+        // The player is fast enough to play again before the monsters.
+        state.set(RunState::AwaitingInput).unwrap();
     } else if *state.current() != RunState::AwaitingInput {
+        if *state.current() == RunState::Monster {
+            // Monsters are the last to play in a turn.
+            turn_counter.turn += 1;
+            if !player_can_act {
+                // The monsters play another turn while the player is busy.
+                return;
+            }
+        }
         RunState::advance_state(&mut state);
     }
 }
 
 /// Run criteria for only running when in game (all states except menu states).
 pub fn run_if_in_game(state: Res<State<RunState>>) -> ShouldRun {
-    if *state.current() != RunState::MainMenu {
-        ShouldRun::Yes
-    } else {
-        ShouldRun::No
+    match state.current() {
+        RunState::MainMenu
+        | RunState::ShowSeedEntry
+        | RunState::NewGame
+        | RunState::ShowLoadSlots
+        | RunState::ShowHighScores
+        | RunState::GameOver => ShouldRun::No,
+        _ => ShouldRun::Yes,
     }
 }
 
@@ -165,6 +336,27 @@ fn main() {
     #[cfg(feature = "trace")]
     tracing_subscriber::fmt::init();
 
+    let args: Vec<String> = std::env::args().collect();
+    // Additional raw files, loaded after the mods.
+    let raws_dir = args
+        .iter()
+        .position(|arg| arg == "--raws")
+        .map(|i| PathBuf::from(args.get(i + 1).expect("--raws needs a directory")));
+
+    // Lint the raws without starting the game.
+    if args.iter().any(|arg| arg == "--check-raws") {
+        let valid = raws::check_raws(raws_dir.as_deref());
+        std::process::exit(if valid { 0 } else { 1 });
+    }
+
+    // Load the raws.
+    if let Err(errors) = raws::load_raws(raws_dir.as_deref()) {
+        for error in &errors {
+            eprintln!("{}", error);
+        }
+        panic!("could not load the raws");
+    }
+
     let mut bterm = BTermBuilder::simple(CONSOLE_WIDTH, CONSOLE_HEIGHT)
         .unwrap()
         .with_title(CONSOLE_TITLE)
@@ -172,9 +364,6 @@ fn main() {
         .unwrap();
     bterm.with_post_scanlines(false);
 
-    // Load the raws.
-    raws::load_spawns();
-
     bevy_app::App::new()
         .add_plugin(CorePlugin)
         .add_stage_after(
@@ -204,17 +393,47 @@ fn main() {
         .add_system_set_to_stage(AppStages::ApplyDamage, State::<RunState>::get_driver())
         .add_system_set_to_stage(AppStages::CleanupAndRender, State::<RunState>::get_driver())
         .add_plugin(BracketLibPlugin::new(bterm))
+        .insert_resource(raws::RawsDir(raws_dir))
         // Initialization logic
         .add_startup_system(init)
+        // Game creation systems.
+        .add_system_set(
+            SystemSet::on_update(RunState::NewGame).with_system(systems::new_game::new_game_system),
+        )
         // Game saving systems.
         .add_system_set(
             SystemSet::on_update(RunState::SaveGame)
                 .with_system(systems::save_game::save_game_system.exclusive_system()),
         )
+        .add_system_set(
+            SystemSet::on_update(RunState::SaveAndQuit)
+                .with_system(systems::save_game::save_game_system.exclusive_system()),
+        )
+        .add_system_set(
+            SystemSet::on_update(RunState::AwaitingInput)
+                .with_system(systems::save_game::autosave_system.exclusive_system()),
+        )
+        // Game loading systems.
+        .add_system_set(
+            SystemSet::on_update(RunState::LoadGame)
+                .with_system(systems::load_game::load_game_system.exclusive_system()),
+        )
         // Handle input first. Input is what triggers the game to update.
         .add_system_set(
             SystemSet::on_update(RunState::AwaitingInput)
-                .with_system(systems::input::player_input_system.label(UpdateLabel::Input)),
+                .with_system(systems::input::player_input_system.label(UpdateLabel::Input))
+                .with_system(systems::reload_raws::reload_raws_system),
+        )
+        // Level transitions. Run before indexing so that the new level is indexed right away.
+        .add_system_set(
+            SystemSet::on_update(RunState::NextLevel).with_system(
+                systems::change_level::change_level_system.before(UpdateLabel::Indexing),
+            ),
+        )
+        .add_system_set(
+            SystemSet::on_update(RunState::PreviousLevel).with_system(
+                systems::change_level::change_level_system.before(UpdateLabel::Indexing),
+            ),
         )
         // Handle player actions.
         .add_system_set(
@@ -249,6 +468,14 @@ fn main() {
                 .with_run_criteria(run_if_in_game)
                 .with_system(systems::melee_combat::melee_combat_system),
         )
+        // Tick status effects and give energy at the end of every turn. Poison and regeneration
+        // are resolved by the damage system.
+        .add_system_set_to_stage(
+            AppStages::ApplyCombat,
+            SystemSet::on_update(RunState::Monster)
+                .with_system(systems::status_effects::status_effect_system)
+                .with_system(systems::energy::energy_system),
+        )
         // Run damage system to apply damage from combat.
         //
         // Previous stage adds damage components. This stage resolves the damage onto the entity's
@@ -293,80 +520,100 @@ fn main() {
                     .after(RenderLabel::Map),
             ),
         )
+        .add_system_set_to_stage(
+            AppStages::CleanupAndRender,
+            SystemSet::on_update(RunState::ShowTargeting).with_system(
+                gui::render_targeting
+                    .label(RenderLabel::UiAndParticles)
+                    .after(RenderLabel::Map),
+            ),
+        )
+        .add_system_set_to_stage(
+            AppStages::CleanupAndRender,
+            SystemSet::on_update(RunState::ShowCharacterSheet).with_system(
+                gui::render_character_sheet
+                    .label(RenderLabel::UiAndParticles)
+                    .after(RenderLabel::Map),
+            ),
+        )
+        .add_system_set_to_stage(
+            AppStages::CleanupAndRender,
+            SystemSet::on_update(RunState::ShowSaveSlots).with_system(
+                render::render_save_slots_menu_system
+                    .label(RenderLabel::UiAndParticles)
+                    .after(RenderLabel::Map),
+            ),
+        )
         .add_system_set_to_stage(
             AppStages::CleanupAndRender,
             SystemSet::on_update(RunState::MainMenu).with_system(render::render_main_menu_system),
         )
+        .add_system_set_to_stage(
+            AppStages::CleanupAndRender,
+            SystemSet::on_enter(RunState::GameOver)
+                .with_system(morgue::write_morgue_system)
+                .with_system(highscores::record_high_score_system),
+        )
+        .add_system_set_to_stage(
+            AppStages::CleanupAndRender,
+            SystemSet::on_update(RunState::ShowSeedEntry)
+                .with_system(render::render_seed_entry_system),
+        )
+        .add_system_set_to_stage(
+            AppStages::CleanupAndRender,
+            SystemSet::on_update(RunState::ShowHighScores)
+                .with_system(render::render_high_scores_system),
+        )
+        .add_system_set_to_stage(
+            AppStages::CleanupAndRender,
+            SystemSet::on_update(RunState::GameOver).with_system(gui::render_game_over),
+        )
+        .add_system_set_to_stage(
+            AppStages::CleanupAndRender,
+            SystemSet::on_update(RunState::ShowLoadSlots)
+                .with_system(render::render_save_slots_menu_system),
+        )
         // Next turn always runs.
         .add_system_to_stage(AppStages::CleanupAndRender, next_turn_state_system)
         .run();
 }
 
-/// Initialization for entities and resources.
-/// TODO: move this into a sub-module.
+/// Initialization for resources.
+///
+/// The game itself is created by [`systems::new_game::new_game_system`] or loaded from a save file.
 fn init(mut commands: Commands) {
-    use components::{
-        CanSufferDamage, CombatStats, EntityName, Player, PlayerBundle, Renderable, Viewshed,
-    };
-    use map_builders::{BspDungeon, MapBuilderChain, RoomBasedSpawner, RoomBasedStartingPosition};
-
-    // Generate map.
-    let mut builder = MapBuilderChain::new(80, 50, 1, BspDungeon)
-        .with(RoomBasedSpawner)
-        .with(RoomBasedStartingPosition);
-
-    let map = builder.build_map();
-    let starting_position = builder.starting_position();
-
-    // Spawn monsters.
-    builder.spawn_entities(&mut commands);
-
-    // Spawn player.
-    let combat_stats = if DEBUG_GOD_MODE {
-        CombatStats {
-            hp: 1000,
-            max_hp: 1000,
-            defense: 1000,
-            power: 1000,
-        }
-    } else {
-        CombatStats {
-            hp: 100,
-            max_hp: 100,
-            defense: 2,
-            power: 5,
-        }
-    };
-    commands.spawn_bundle(PlayerBundle {
-        player: Player,
-        name: EntityName {
-            name: "Player".to_string(),
-        },
-        position: starting_position,
-        renderable: Renderable {
-            glyph: '@' as u16,
-            fg: RGB::named(YELLOW),
-            bg: RGB::named(BLACK),
-            z_index: 3,
-        },
-        viewshed: Viewshed::new(8),
-        combat_stats,
-        can_suffer_damage: CanSufferDamage::default(),
-    });
-
     // Spawn resources.
 
-    // Tile map resource.
-    commands.insert_resource(map);
+    // Tile map resource. Replaced when a game is started.
+    commands.insert_resource(map::Map::new(0, 0, 0));
+    commands.insert_resource(map::LevelStore::default());
+    commands.insert_resource(rng::GameRng::from_entropy());
     // Game log resource.
     commands.insert_resource(gamelog::GameLog {
         entries: Mutex::new(vec!["Welcome to Tailarc!".to_string()]),
     });
+    commands.insert_resource(TurnCounter::default());
+    commands.insert_resource(save::SaveSlots::default());
+    commands.insert_resource(save::ActiveSlot::default());
+    commands.insert_resource(save::Autosave::from_env());
+    commands.insert_resource(save::Run::default());
+    commands.insert_resource(morgue::KillCounts::default());
+    commands.insert_resource(morgue::Morgue::default());
+    commands.insert_resource(highscores::HighScores::default());
+    commands.insert_resource(render::HighScoresResult::NoResponse);
     commands.insert_resource(render::MainMenuResult::NoSelection {
         selected: render::MainMenuSelection::NewGame,
     });
+    commands.insert_resource(render::SeedEntryResult::NoSelection {
+        text: String::new(),
+    });
+    commands.insert_resource(render::SaveSlotMenuResult::NoSelection { selected: 0 });
     commands.insert_resource(gui::ItemMenuResult::NoResponse);
     commands.insert_resource(gui::DropItemResult::NoResponse);
+    commands.insert_resource(gui::CharacterSheetResult::NoResponse);
+    commands.insert_resource(gui::Targeting::default());
+    commands.insert_resource(gui::TargetingResult::NoResponse);
+    commands.insert_resource(gui::GameOverResult::NoSelection);
     commands.insert_resource(systems::particle::ParticleBuilder::new());
 
     tracing::info!("Finished initialization");
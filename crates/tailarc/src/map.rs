@@ -1,18 +1,31 @@
 //! Tilemap.
 
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 
 use bevy_ecs::prelude::*;
 use bracket_lib::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+/// Width of the levels.
+pub const MAP_WIDTH: u32 = 80;
+/// Height of the levels.
+pub const MAP_HEIGHT: u32 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Tile {
     Wall,
     Floor,
+    /// Leads to the next level.
+    DownStairs,
+    /// Leads to the previous level.
+    UpStairs,
 }
 
 /// Represents a single tile of the map and its properties.
-#[derive(Debug, Clone, PartialEq)]
+///
+/// Fields that are recomputed every tick are not serialized. Call [`Map::reset_indexes`] after
+/// deserializing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Map {
     /// A vec of [`Tile`]s.
     pub tiles: Vec<Tile>,
@@ -23,12 +36,15 @@ pub struct Map {
     /// Tiles that are visible to the player (inside FOV).
     ///
     /// Updated in [`visibility`](crate::systems::visibility) system.
+    #[serde(skip)]
     pub visible_tiles: Vec<bool>,
     /// An index of what is in each tile.
+    #[serde(skip)]
     pub tile_content: Vec<Vec<Entity>>,
     /// Tiles that are blocked (e.g. walls, monsters, etc...).
     ///
     /// Updated in [`map_indexing`](crate::systems::map_indexing) system.
+    #[serde(skip)]
     pub blocked: Vec<bool>,
 
     /// Width of the tile map.
@@ -59,6 +75,21 @@ impl Map {
         }
     }
 
+    /// Resizes and clears the fields that are not serialized (`visible_tiles`, `tile_content` and
+    /// `blocked`).
+    pub fn reset_indexes(&mut self) {
+        let tile_map_size = (self.width * self.height) as usize;
+        self.visible_tiles = vec![false; tile_map_size];
+        self.tile_content = vec![Vec::new(); tile_map_size];
+        self.blocked = vec![false; tile_map_size];
+        self.populate_blocked();
+    }
+
+    /// Returns the index of the first tile of the given type.
+    pub fn find_tile(&self, tile: Tile) -> Option<usize> {
+        self.tiles.iter().position(|&t| t == tile)
+    }
+
     pub fn populate_blocked(&mut self) {
         for (i, &tile) in self.tiles.iter().enumerate() {
             self.blocked[i] = tile == Tile::Wall;
@@ -81,7 +112,7 @@ impl Map {
     }
 
     /// Returns true if the tile is within the map boundaries and is not blocked.
-    fn is_exit_valid(&self, x: i32, y: i32) -> bool {
+    pub fn is_exit_valid(&self, x: i32, y: i32) -> bool {
         if x < 1 || x > self.width as i32 - 1 || y < 1 || y > self.height as i32 - 1 {
             return false;
         }
@@ -145,3 +176,12 @@ impl BaseMap for Map {
         DistanceAlg::Pythagoras.distance2d(p1, p2)
     }
 }
+
+/// A resource containing the levels that the player is not on, keyed by depth.
+///
+/// The entities of these levels are kept in the world with an
+/// [`OtherLevelPosition`](crate::components::OtherLevelPosition) instead of a `Position`.
+#[derive(Debug, Default, Clone)]
+pub struct LevelStore {
+    pub levels: BTreeMap<i32, Map>,
+}
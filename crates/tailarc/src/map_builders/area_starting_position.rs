@@ -3,7 +3,7 @@ use bracket_lib::prelude::*;
 use crate::components::Position;
 use crate::map::Tile;
 
-use super::{MapBuilder, MetaMapBuilder};
+use super::{BuildFeatures, MapBuilder, MetaMapBuilder};
 
 pub enum XStart {
     Left,
@@ -70,4 +70,11 @@ impl MetaMapBuilder for AreaStartingPosition {
             y: closest_idx as u32 / build_data.map.width,
         });
     }
+
+    fn provides(&self) -> BuildFeatures {
+        BuildFeatures {
+            starting_position: true,
+            ..BuildFeatures::default()
+        }
+    }
 }
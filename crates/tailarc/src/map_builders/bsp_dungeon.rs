@@ -1,17 +1,18 @@
-use rand::prelude::{SliceRandom, ThreadRng};
-use rand::{thread_rng, Rng};
+use rand::prelude::SliceRandom;
+use rand::rngs::StdRng;
+use rand::Rng;
 
 use crate::map::{Map, Tile};
 use crate::map_builders::common::apply_room_to_map;
 
-use super::{InitialMapBuilder, Rect};
+use super::{BuildFeatures, InitialMapBuilder, Rect};
 
 /// Map generation using Binary Space Partition algorithm.
 pub struct BspDungeon;
 
 impl InitialMapBuilder for BspDungeon {
     fn build_map(&mut self, build_data: &mut super::MapBuilder) {
-        let mut rng = thread_rng();
+        let rng = &mut build_data.rng;
 
         // We want a ratio of 1 split for every 16 tiles.
         let n = build_data.map.width * build_data.map.height / 16;
@@ -30,8 +31,8 @@ impl InitialMapBuilder for BspDungeon {
 
         for _i in 0..n {
             // Rationale: a least one element because of first_room.
-            let rect = *rects.choose(&mut rng).unwrap();
-            let candidate = get_random_subrect(rect, &mut rng);
+            let rect = *rects.choose(rng).unwrap();
+            let candidate = get_random_subrect(rect, rng);
 
             if is_possible(&build_data.map, candidate) {
                 apply_room_to_map(&mut build_data.map, &candidate);
@@ -57,6 +58,13 @@ impl InitialMapBuilder for BspDungeon {
 
         build_data.rooms = Some(rooms);
     }
+
+    fn provides(&self) -> BuildFeatures {
+        BuildFeatures {
+            rooms: true,
+            ..BuildFeatures::default()
+        }
+    }
 }
 
 /// Divide a rectangle into 4 quadrants.
@@ -78,7 +86,7 @@ fn add_subrects(rects: &mut Vec<Rect>, r: Rect) {
 
 /// Returns a [`Rect`] with random dimensions that fits inside `r`.
 /// The dimensions are at least 4x4 and at most 10x10.
-fn get_random_subrect(mut r: Rect, rng: &mut ThreadRng) -> Rect {
+fn get_random_subrect(mut r: Rect, rng: &mut StdRng) -> Rect {
     let outer_w = r.width();
     let outer_h = r.height();
 
@@ -1,9 +1,9 @@
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
 use crate::components::Position;
 use crate::map::Tile;
 
-use super::InitialMapBuilder;
+use super::{BuildFeatures, InitialMapBuilder};
 
 /// Number of iterations to apply cellular automata to the map.
 const NUM_ITERATIONS: usize = 20;
@@ -12,12 +12,18 @@ pub struct CellularAutomata;
 
 impl InitialMapBuilder for CellularAutomata {
     fn build_map(&mut self, build_data: &mut super::MapBuilder) {
-        let mut rng = thread_rng();
-
-        // Fill the map with 55% floor and 45% wall.
-        for tile in &mut build_data.map.tiles {
-            let roll = rng.gen_range(0..100);
-            if roll < 45 {
+        // Fill the map with 55% floor and 45% wall. The edges are always walls, the cellular
+        // automata rules below do not change them.
+        let (width, height) = (
+            build_data.map.width as usize,
+            build_data.map.height as usize,
+        );
+        for (idx, tile) in build_data.map.tiles.iter_mut().enumerate() {
+            let (x, y) = (idx % width, idx / width);
+            let roll = build_data.rng.gen_range(0..100);
+            if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                *tile = Tile::Wall;
+            } else if roll < 45 {
                 *tile = Tile::Floor;
             } else {
                 *tile = Tile::Wall;
@@ -88,4 +94,11 @@ impl InitialMapBuilder for CellularAutomata {
             build_data.map.tiles = newtiles;
         }
     }
+
+    fn provides(&self) -> BuildFeatures {
+        BuildFeatures {
+            starting_position: true,
+            ..BuildFeatures::default()
+        }
+    }
 }
@@ -2,7 +2,7 @@ use bracket_lib::prelude::DijkstraMap;
 
 use crate::map::Tile;
 
-use super::MetaMapBuilder;
+use super::{BuildFeatures, MetaMapBuilder};
 
 /// Remove areas that are not reachable from the starting position.
 pub struct CullUnreachable;
@@ -35,4 +35,11 @@ impl MetaMapBuilder for CullUnreachable {
             }
         }
     }
+
+    fn requirements(&self) -> BuildFeatures {
+        BuildFeatures {
+            starting_position: true,
+            ..BuildFeatures::default()
+        }
+    }
 }
@@ -0,0 +1,47 @@
+use bracket_lib::prelude::DijkstraMap;
+
+use crate::map::Tile;
+
+use super::{BuildFeatures, MapBuilder, MetaMapBuilder};
+
+/// Places the stairs down on the reachable floor tile that is the farthest from the starting
+/// position.
+pub struct DistantExit;
+
+impl MetaMapBuilder for DistantExit {
+    fn build_map(&mut self, build_data: &mut MapBuilder) {
+        let starting_position = build_data
+            .starting_position
+            .expect("DistantExit needs a starting position");
+        let start_idx = build_data
+            .map
+            .xy_idx(starting_position.x, starting_position.y);
+        build_data.map.populate_blocked();
+
+        let map_starts = vec![start_idx];
+        let dijkstra_map = DijkstraMap::new(
+            build_data.map.width as usize,
+            build_data.map.height as usize,
+            &map_starts,
+            &build_data.map,
+            1000.0,
+        );
+        let exit_idx = build_data
+            .map
+            .tiles
+            .iter()
+            .enumerate()
+            .filter(|&(idx, &tile)| tile == Tile::Floor && dijkstra_map.map[idx] != f32::MAX)
+            .max_by(|a, b| dijkstra_map.map[a.0].total_cmp(&dijkstra_map.map[b.0]))
+            .map(|(idx, _)| idx)
+            .expect("at least one reachable floor tile");
+        build_data.map.tiles[exit_idx] = Tile::DownStairs;
+    }
+
+    fn requirements(&self) -> BuildFeatures {
+        BuildFeatures {
+            starting_position: true,
+            ..BuildFeatures::default()
+        }
+    }
+}
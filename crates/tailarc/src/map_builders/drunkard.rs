@@ -1,4 +1,4 @@
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
 use crate::map::Tile;
 
@@ -18,8 +18,6 @@ pub struct DrunkardsWalk {
 
 impl InitialMapBuilder for DrunkardsWalk {
     fn build_map(&mut self, build_data: &mut super::MapBuilder) {
-        let mut rng = thread_rng();
-
         let total_tiles = build_data.map.width * build_data.map.height;
         let desired_floor_tiles = (self.floor_percent * total_tiles as f32) as usize;
 
@@ -34,8 +32,8 @@ impl InitialMapBuilder for DrunkardsWalk {
             let (mut x, mut y) = match self.spawn_mode {
                 DrunkardSpawnMode::Center => (build_data.map.width / 2, build_data.map.height / 2),
                 DrunkardSpawnMode::Random => (
-                    rng.gen_range(1..build_data.map.width - 2),
-                    rng.gen_range(1..build_data.map.height - 2),
+                    build_data.rng.gen_range(1..build_data.map.width - 2),
+                    build_data.rng.gen_range(1..build_data.map.height - 2),
                 ),
             };
             let idx = build_data.map.xy_idx(x, y);
@@ -43,7 +41,7 @@ impl InitialMapBuilder for DrunkardsWalk {
 
             // Make the drunkard walk around.
             for _i in 0..self.lifetime {
-                let direction = rng.gen_range(0..4);
+                let direction = build_data.rng.gen_range(0..4);
                 match direction {
                     // Up.
                     0 => {
@@ -5,17 +5,24 @@ mod bsp_dungeon;
 mod cellular_automata;
 mod common;
 mod cull_unreachable;
+mod distant_exit;
 mod drunkard;
 mod room_based_spawner;
+mod room_based_stairs;
 mod room_based_starting_position;
 mod simple_map;
 mod spawner;
+mod up_stairs;
+mod voronoi_spawning;
 
 use bevy_ecs::prelude::Commands;
+use rand::rngs::StdRng;
+use rand::Rng;
 
 use crate::components::Position;
 use crate::map::Map;
 use crate::raws::{spawn_named_entity, SpawnType};
+use crate::rng::level_rng;
 
 use self::common::*;
 
@@ -23,10 +30,14 @@ pub use self::area_starting_position::{AreaStartingPosition, XStart, YStart};
 pub use self::bsp_dungeon::BspDungeon;
 pub use self::cellular_automata::CellularAutomata;
 pub use self::cull_unreachable::CullUnreachable;
+pub use self::distant_exit::DistantExit;
 pub use self::drunkard::{DrunkardSpawnMode, DrunkardsWalk};
 pub use self::room_based_spawner::RoomBasedSpawner;
+pub use self::room_based_stairs::RoomBasedStairs;
 pub use self::room_based_starting_position::RoomBasedStartingPosition;
 pub use self::simple_map::SimpleMap;
+pub use self::up_stairs::UpStairsAtStart;
+pub use self::voronoi_spawning::VoronoiSpawning;
 
 /// Contains the data used by map builders.
 pub struct MapBuilder {
@@ -35,6 +46,9 @@ pub struct MapBuilder {
     pub rooms: Option<Vec<Rect>>,
     pub corridors: Option<Vec<Vec<usize>>>,
     pub spawn_list: Vec<(Position, String)>,
+    /// The RNG that must be used for all the randomness of the level so that it can be reproduced
+    /// from its seed.
+    pub rng: StdRng,
 }
 
 /// A chain of map builders.
@@ -48,10 +62,13 @@ pub struct MapBuilderChain {
 impl MapBuilderChain {
     /// Create a new [`MapBuilderChain`] with the specified dimensions, depth, and
     /// [`InitialMapBuilder`].
+    ///
+    /// The same seed and depth always generate the same level.
     pub fn new(
         width: u32,
         height: u32,
         depth: i32,
+        seed: u64,
         starter: impl InitialMapBuilder + 'static,
     ) -> Self {
         Self {
@@ -63,6 +80,7 @@ impl MapBuilderChain {
                 rooms: None,
                 corridors: None,
                 spawn_list: Vec::new(),
+                rng: level_rng(seed, depth),
             },
         }
     }
@@ -99,12 +117,171 @@ impl MapBuilderChain {
     }
 }
 
+/// Returns the [`InitialMapBuilder`]s that levels can be generated from.
+fn starters() -> Vec<Box<dyn InitialMapBuilder>> {
+    vec![
+        Box::new(BspDungeon),
+        Box::new(SimpleMap),
+        Box::new(CellularAutomata),
+        Box::new(DrunkardsWalk::open_area()),
+        Box::new(DrunkardsWalk::open_halls()),
+        Box::new(DrunkardsWalk::winding_passages()),
+    ]
+}
+
+/// Picks one of the builders whose requirements are met by the features already built.
+///
+/// Returns `None` if no builder can be used.
+fn pick(
+    rng: &mut StdRng,
+    built: BuildFeatures,
+    candidates: Vec<Box<dyn MetaMapBuilder>>,
+) -> Option<Box<dyn MetaMapBuilder>> {
+    let mut candidates: Vec<_> = candidates
+        .into_iter()
+        .filter(|builder| built.satisfies(builder.requirements()))
+        .collect();
+    if candidates.is_empty() {
+        return None;
+    }
+    let idx = rng.gen_range(0..candidates.len());
+    Some(candidates.swap_remove(idx))
+}
+
+/// Returns the [`MapBuilderChain`] used to generate the level at the given depth.
+///
+/// The builders are picked at random among all the available ones, so that levels look different.
+/// A [`MetaMapBuilder`] is only used if its [requirements](MetaMapBuilder::requirements) are met
+/// by the builders before it. The same seed and depth always give the same chain.
+pub fn level_builder(width: u32, height: u32, depth: i32, seed: u64) -> MapBuilderChain {
+    // The chain must not depend on anything else than the seed and the depth.
+    let mut rng = level_rng(seed, depth);
+
+    let mut starters = starters();
+    let starter = starters.swap_remove(rng.gen_range(0..starters.len()));
+    level_builder_with_starter(width, height, depth, seed, starter, rng)
+}
+
+/// Returns the [`MapBuilderChain`] used to generate the level at the given depth, starting with
+/// `starter`. `rng` is used to pick the other builders.
+fn level_builder_with_starter(
+    width: u32,
+    height: u32,
+    depth: i32,
+    seed: u64,
+    starter: Box<dyn InitialMapBuilder>,
+    mut rng: StdRng,
+) -> MapBuilderChain {
+    let mut built = starter.provides();
+    let mut builder = MapBuilderChain::new(width, height, depth, seed, starter);
+
+    // Start in a room when there are rooms, so that the stairs are not in the same room.
+    let start: Box<dyn MetaMapBuilder> = if built.rooms {
+        Box::new(RoomBasedStartingPosition)
+    } else {
+        let x = match rng.gen_range(0..3) {
+            0 => XStart::Left,
+            1 => XStart::Center,
+            _ => XStart::Right,
+        };
+        let y = match rng.gen_range(0..3) {
+            0 => YStart::Top,
+            1 => YStart::Middle,
+            _ => YStart::Bottom,
+        };
+        Box::new(AreaStartingPosition::new(x, y))
+    };
+    let steps: Vec<Vec<Box<dyn MetaMapBuilder>>> = vec![
+        vec![start],
+        // Open maps may have areas that cannot be reached from the start.
+        vec![Box::new(CullUnreachable)],
+        vec![Box::new(RoomBasedSpawner), Box::new(VoronoiSpawning)],
+        vec![Box::new(RoomBasedStairs), Box::new(DistantExit)],
+    ];
+    for candidates in steps {
+        if let Some(meta_builder) = pick(&mut rng, built, candidates) {
+            built = built.with(meta_builder.provides());
+            builder = builder.with(meta_builder);
+        }
+    }
+
+    // There is nothing above the first level.
+    if depth > 1 {
+        builder = builder.with(UpStairsAtStart);
+    }
+    builder.build_data.rng = rng;
+    builder
+}
+
+/// Optional data of [`MapBuilder`] that builders set or rely on.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BuildFeatures {
+    /// [`MapBuilder::rooms`] is set.
+    pub rooms: bool,
+    /// [`MapBuilder::starting_position`] is set.
+    pub starting_position: bool,
+}
+
+impl BuildFeatures {
+    /// Returns true if all the features of `required` are in `self`.
+    pub fn satisfies(self, required: BuildFeatures) -> bool {
+        (self.rooms || !required.rooms) && (self.starting_position || !required.starting_position)
+    }
+
+    /// Returns the union of both sets of features.
+    pub fn with(self, other: BuildFeatures) -> BuildFeatures {
+        BuildFeatures {
+            rooms: self.rooms || other.rooms,
+            starting_position: self.starting_position || other.starting_position,
+        }
+    }
+}
+
 pub trait InitialMapBuilder {
     fn build_map(&mut self, build_data: &mut MapBuilder);
+
+    /// Returns the optional data that this builder sets.
+    fn provides(&self) -> BuildFeatures {
+        BuildFeatures::default()
+    }
 }
 
 pub trait MetaMapBuilder {
     fn build_map(&mut self, build_data: &mut MapBuilder);
+
+    /// Returns the optional data that must be set before this builder runs.
+    fn requirements(&self) -> BuildFeatures {
+        BuildFeatures::default()
+    }
+
+    /// Returns the optional data that this builder sets.
+    fn provides(&self) -> BuildFeatures {
+        BuildFeatures::default()
+    }
+}
+
+impl<B: InitialMapBuilder + ?Sized> InitialMapBuilder for Box<B> {
+    fn build_map(&mut self, build_data: &mut MapBuilder) {
+        (**self).build_map(build_data)
+    }
+
+    fn provides(&self) -> BuildFeatures {
+        (**self).provides()
+    }
+}
+
+impl<B: MetaMapBuilder + ?Sized> MetaMapBuilder for Box<B> {
+    fn build_map(&mut self, build_data: &mut MapBuilder) {
+        (**self).build_map(build_data)
+    }
+
+    fn requirements(&self) -> BuildFeatures {
+        (**self).requirements()
+    }
+
+    fn provides(&self) -> BuildFeatures {
+        (**self).provides()
+    }
 }
 
 /// Rectangle dimensions and position.
@@ -153,3 +330,58 @@ impl Rect {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Tile;
+    use crate::raws::load_raws;
+
+    fn build(depth: i32, seed: u64) -> (Vec<Tile>, Vec<(Position, String)>, Position) {
+        load_raws(None).unwrap();
+        let mut builder = level_builder(80, 50, depth, seed);
+        let map = builder.build_map();
+        (
+            map.tiles,
+            builder.build_data.spawn_list.clone(),
+            builder.starting_position(),
+        )
+    }
+
+    #[test]
+    fn same_seed_and_depth_build_same_level() {
+        for seed in [0, 1, 20261007] {
+            assert_eq!(build(1, seed), build(1, seed));
+        }
+    }
+
+    #[test]
+    fn all_starters_build_levels_with_stairs() {
+        load_raws(None).unwrap();
+        for idx in 0..starters().len() {
+            for seed in 0..5 {
+                for depth in 1..=3 {
+                    let starter = starters().swap_remove(idx);
+                    let rng = level_rng(seed, depth);
+                    let mut builder = level_builder_with_starter(80, 50, depth, seed, starter, rng);
+                    let map = builder.build_map();
+                    let context = format!("starter {}, seed {}, depth {}", idx, seed, depth);
+                    assert!(map.find_tile(Tile::DownStairs).is_some(), "{}", context);
+                    assert_eq!(
+                        map.find_tile(Tile::UpStairs).is_some(),
+                        depth > 1,
+                        "{}",
+                        context
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn different_seed_or_depth_build_different_levels() {
+        let (tiles, ..) = build(1, 1);
+        assert_ne!(build(1, 2).0, tiles);
+        assert_ne!(build(2, 1).0, tiles);
+    }
+}
@@ -1,5 +1,5 @@
-use super::spawner::spawn_room;
-use super::{MapBuilder, MetaMapBuilder};
+use super::spawner::{max_spawns, spawn_room};
+use super::{BuildFeatures, MapBuilder, MetaMapBuilder};
 
 pub struct RoomBasedSpawner;
 
@@ -10,8 +10,23 @@ impl MetaMapBuilder for RoomBasedSpawner {
             .as_ref()
             .expect("rooms required for RoomBasedSpawner");
 
+        let depth = build_data.map.depth;
         for room in rooms.iter().skip(1) {
-            spawn_room(&mut build_data.spawn_list, room, 3, 4);
+            spawn_room(
+                &mut build_data.spawn_list,
+                room,
+                depth,
+                max_spawns(3, depth),
+                max_spawns(4, depth),
+                &mut build_data.rng,
+            );
+        }
+    }
+
+    fn requirements(&self) -> BuildFeatures {
+        BuildFeatures {
+            rooms: true,
+            ..BuildFeatures::default()
         }
     }
 }
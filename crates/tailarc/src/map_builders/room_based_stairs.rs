@@ -0,0 +1,25 @@
+use crate::map::Tile;
+
+use super::{BuildFeatures, MapBuilder, MetaMapBuilder};
+
+/// Places the stairs down in the center of the last room.
+pub struct RoomBasedStairs;
+
+impl MetaMapBuilder for RoomBasedStairs {
+    fn build_map(&mut self, build_data: &mut MapBuilder) {
+        let rooms = build_data
+            .rooms
+            .as_ref()
+            .expect("rooms required for RoomBasedStairs");
+        let (x, y) = rooms.last().expect("at least one room").center();
+        let idx = build_data.map.xy_idx(x, y);
+        build_data.map.tiles[idx] = Tile::DownStairs;
+    }
+
+    fn requirements(&self) -> BuildFeatures {
+        BuildFeatures {
+            rooms: true,
+            ..BuildFeatures::default()
+        }
+    }
+}
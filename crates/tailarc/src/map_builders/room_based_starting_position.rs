@@ -1,6 +1,6 @@
 use crate::components::Position;
 
-use super::{MapBuilder, MetaMapBuilder};
+use super::{BuildFeatures, MapBuilder, MetaMapBuilder};
 
 pub struct RoomBasedStartingPosition;
 
@@ -13,4 +13,18 @@ impl MetaMapBuilder for RoomBasedStartingPosition {
         let (x, y) = rooms[0].center();
         build_data.starting_position = Some(Position { x, y });
     }
+
+    fn requirements(&self) -> BuildFeatures {
+        BuildFeatures {
+            rooms: true,
+            ..BuildFeatures::default()
+        }
+    }
+
+    fn provides(&self) -> BuildFeatures {
+        BuildFeatures {
+            starting_position: true,
+            ..BuildFeatures::default()
+        }
+    }
 }
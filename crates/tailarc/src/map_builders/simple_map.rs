@@ -1,8 +1,8 @@
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
 use super::{
-    apply_horizontal_tunnel, apply_room_to_map, apply_vertical_tunnel, InitialMapBuilder,
-    MapBuilder, Rect,
+    apply_horizontal_tunnel, apply_room_to_map, apply_vertical_tunnel, BuildFeatures,
+    InitialMapBuilder, MapBuilder, Rect,
 };
 
 pub struct SimpleMap;
@@ -12,16 +12,14 @@ impl SimpleMap {
         const MIN_SIZE: u32 = 6;
         const MAX_SIZE: u32 = 10;
 
-        let mut rng = thread_rng();
-
         let max_rooms = (build_data.map.width * build_data.map.height) / 100;
         let mut rooms = Vec::new();
 
         for _ in 0..max_rooms {
-            let w = rng.gen_range(MIN_SIZE..MAX_SIZE);
-            let h = rng.gen_range(MIN_SIZE..MAX_SIZE);
-            let x = rng.gen_range(1..build_data.map.width - w - 2);
-            let y = rng.gen_range(1..build_data.map.height - h - 2);
+            let w = build_data.rng.gen_range(MIN_SIZE..MAX_SIZE);
+            let h = build_data.rng.gen_range(MIN_SIZE..MAX_SIZE);
+            let x = build_data.rng.gen_range(1..build_data.map.width - w - 2);
+            let y = build_data.rng.gen_range(1..build_data.map.height - h - 2);
             let new_room = Rect::new(x, y, w, h);
             let mut ok = true;
             for other_room in rooms.iter() {
@@ -35,7 +33,7 @@ impl SimpleMap {
                 if !rooms.is_empty() {
                     let (new_x, new_y) = new_room.center();
                     let (prev_x, prev_y) = rooms.last().unwrap().center();
-                    if rng.gen::<bool>() {
+                    if build_data.rng.gen::<bool>() {
                         apply_horizontal_tunnel(&mut build_data.map, prev_x, new_x, prev_y);
                         apply_vertical_tunnel(&mut build_data.map, prev_y, new_y, new_x);
                     } else {
@@ -55,4 +53,11 @@ impl InitialMapBuilder for SimpleMap {
     fn build_map(&mut self, build_data: &mut MapBuilder) {
         self.new_map_rooms_and_corridors(build_data);
     }
+
+    fn provides(&self) -> BuildFeatures {
+        BuildFeatures {
+            rooms: true,
+            ..BuildFeatures::default()
+        }
+    }
 }
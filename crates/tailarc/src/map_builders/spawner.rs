@@ -1,102 +1,195 @@
 //! Utilities for spawning entities in levels.
 
-use rand::seq::IteratorRandom;
-use rand::{thread_rng, Rng};
+use std::collections::HashMap;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
 
 use crate::components::Position;
-use crate::raws::RAW_MANAGER;
+use crate::raws::{SpawnTableEntry, RAW_MANAGER};
 
 use super::Rect;
 
+/// Returns the maximum number of monsters or items in an area at the given depth, where `base` is
+/// the maximum at depth 1. Deeper levels are more crowded.
+pub fn max_spawns(base: u32, depth: i32) -> u32 {
+    base + (depth.max(1) as u32 - 1) / 2
+}
+
 /// Fills a room with stuff!
 pub fn spawn_room(
     spawn_list: &mut Vec<(Position, String)>,
     room: &Rect,
+    depth: i32,
     max_monsters: u32,
     max_items: u32,
+    rng: &mut StdRng,
 ) {
-    let mut monster_spawn_points: Vec<Position> = Vec::new();
-    let mut item_spawn_points: Vec<Position> = Vec::new();
-
-    let mut rng = thread_rng();
-    let num_monsters = rng.gen_range(1..=max_monsters);
-    let num_items = rng.gen_range(1..=max_items);
-
-    for _i in 0..num_monsters {
-        let mut added = false;
-        while !added {
-            // Generate random coordinates x and y inside room.
-            let x = room.x1 + rng.gen_range(1..room.width());
-            let y = room.y1 + rng.gen_range(1..room.height());
-            // Ensure that the coordinates are inside the room.
-            debug_assert!(room.x1 < x && x < room.x2);
-            debug_assert!(room.y1 < y && y < room.y2);
-
-            let pos = Position { x, y };
-            // Check that there is not already a monster at that position.
-            if !monster_spawn_points.contains(&pos) {
-                monster_spawn_points.push(pos);
-                added = true;
-            }
+    // Only the inside of the room, without its walls.
+    let mut area = Vec::new();
+    for y in room.y1 + 1..room.y2 {
+        for x in room.x1 + 1..room.x2 {
+            area.push(Position { x, y });
         }
     }
+    spawn_area(spawn_list, &area, depth, max_monsters, max_items, rng);
+}
 
-    for _i in 0..num_items {
-        let mut added = false;
-        while !added {
-            // Generate random coordinates x and y inside room.
-            let x = room.x1 + rng.gen_range(1..room.width());
-            let y = room.y1 + rng.gen_range(1..room.height());
-            // Ensure that the coordinates are inside the room.
-            debug_assert!(room.x1 < x && x < room.x2);
-            debug_assert!(room.y1 < y && y < room.y2);
-
-            let pos = Position { x, y };
-            // Check that there is not already an item at that position.
-            if !item_spawn_points.contains(&pos) {
-                item_spawn_points.push(pos);
-                added = true;
-            }
-        }
+/// Fills an area with stuff!
+///
+/// The area is a list of floor positions. It does not need to be a rectangle. The monsters and
+/// items are rolled from the spawn table for the given depth.
+pub fn spawn_area(
+    spawn_list: &mut Vec<(Position, String)>,
+    area: &[Position],
+    depth: i32,
+    max_monsters: u32,
+    max_items: u32,
+    rng: &mut StdRng,
+) {
+    if area.is_empty() {
+        return;
     }
 
+    // There cannot be more than one monster or more than one item on each position.
+    let num_monsters = rng.gen_range(1..=max_monsters).min(area.len() as u32);
+    let num_items = rng.gen_range(1..=max_items).min(area.len() as u32);
+
+    let monster_spawn_points: Vec<Position> = area
+        .choose_multiple(rng, num_monsters as usize)
+        .copied()
+        .collect();
+    let item_spawn_points: Vec<Position> = area
+        .choose_multiple(rng, num_items as usize)
+        .copied()
+        .collect();
+
+    let raw_manager = RAW_MANAGER.read();
+
     // Actually spawn the monsters.
     for &pos in monster_spawn_points.iter() {
-        spawn_random_monster(spawn_list, pos);
+        if let Some(name) = roll_spawn_table(
+            &raw_manager.raws.spawn_table,
+            &raw_manager.mob_index,
+            depth,
+            rng,
+        ) {
+            spawn_list.push((pos, name));
+        }
     }
 
     // Actually spawn the items.
     for &pos in item_spawn_points.iter() {
-        spawn_random_item(spawn_list, pos);
+        if let Some(name) = roll_spawn_table(
+            &raw_manager.raws.spawn_table,
+            &raw_manager.item_index,
+            depth,
+            rng,
+        ) {
+            spawn_list.push((pos, name));
+        }
     }
 }
 
-/// Spawn a random monster at the specified position.
-fn spawn_random_monster(spawn_list: &mut Vec<(Position, String)>, pos: Position) {
-    let mut rng = thread_rng();
+/// Picks a random entry of the spawn table that can appear at the given depth, among the entries
+/// whose name is in `index`.
+///
+/// Returns `None` if no such entry exists.
+fn roll_spawn_table(
+    spawn_table: &[SpawnTableEntry],
+    index: &HashMap<String, usize>,
+    depth: i32,
+    rng: &mut StdRng,
+) -> Option<String> {
+    // The table is a `Vec` so that the order of the entries, and thus the level, is deterministic.
+    let entries: Vec<&SpawnTableEntry> = spawn_table
+        .iter()
+        .filter(|entry| {
+            index.contains_key(&entry.name) && entry.min_depth <= depth && depth <= entry.max_depth
+        })
+        .collect();
+
+    entries
+        .choose_weighted(rng, |entry| entry.weight)
+        .ok()
+        .map(|entry| entry.name.clone())
+}
 
-    let raw_manager = RAW_MANAGER.read();
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
 
-    let (mob, _) = raw_manager
-        .mob_index
-        .iter()
-        .choose(&mut rng)
-        .expect("mob_index is not empty");
+    use super::*;
 
-    spawn_list.push((pos, mob.clone()));
-}
+    fn entry(name: &str, weight: u32, min_depth: i32, max_depth: i32) -> SpawnTableEntry {
+        SpawnTableEntry {
+            name: name.to_string(),
+            weight,
+            min_depth,
+            max_depth,
+        }
+    }
 
-/// Spawn a random monster at the specified position.
-fn spawn_random_item(spawn_list: &mut Vec<(Position, String)>, pos: Position) {
-    let mut rng = thread_rng();
+    fn index(names: &[&str]) -> HashMap<String, usize> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(idx, name)| (name.to_string(), idx))
+            .collect()
+    }
 
-    let raw_manager = RAW_MANAGER.read();
+    #[test]
+    fn roll_only_entries_of_the_depth() {
+        let spawn_table = vec![
+            entry("Goblin", 10, 1, 3),
+            entry("Orc", 10, 2, 5),
+            entry("Dragon", 10, 10, 10),
+        ];
+        let index = index(&["Goblin", "Orc", "Dragon"]);
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let name = roll_spawn_table(&spawn_table, &index, 1, &mut rng);
+            assert_eq!(name.as_deref(), Some("Goblin"));
+            let name = roll_spawn_table(&spawn_table, &index, 4, &mut rng);
+            assert_eq!(name.as_deref(), Some("Orc"));
+        }
+    }
 
-    let (item, _) = raw_manager
-        .item_index
-        .iter()
-        .choose(&mut rng)
-        .expect("item_index is not empty");
+    #[test]
+    fn roll_nothing_without_matching_entries() {
+        let spawn_table = vec![entry("Goblin", 10, 1, 3), entry("Orc", 10, 2, 5)];
+        let mut rng = StdRng::seed_from_u64(0);
+        let all = index(&["Goblin", "Orc"]);
+        assert_eq!(roll_spawn_table(&spawn_table, &all, 6, &mut rng), None);
+        assert_eq!(roll_spawn_table(&[], &all, 1, &mut rng), None);
+        // Only the entries in the index can be rolled.
+        let orcs = index(&["Orc"]);
+        assert_eq!(roll_spawn_table(&spawn_table, &orcs, 1, &mut rng), None);
+    }
+
+    #[test]
+    fn never_roll_zero_weight_entries() {
+        let spawn_table = vec![entry("Goblin", 0, 1, 3), entry("Orc", 1, 1, 3)];
+        let index = index(&["Goblin", "Orc"]);
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let name = roll_spawn_table(&spawn_table, &index, 1, &mut rng);
+            assert_eq!(name.as_deref(), Some("Orc"));
+        }
+
+        let spawn_table = vec![entry("Goblin", 0, 1, 3)];
+        assert_eq!(roll_spawn_table(&spawn_table, &index, 1, &mut rng), None);
+    }
 
-    spawn_list.push((pos, item.clone()));
+    #[test]
+    fn more_spawns_deeper() {
+        assert_eq!(max_spawns(4, 1), 4);
+        assert_eq!(max_spawns(4, 2), 4);
+        assert_eq!(max_spawns(4, 3), 5);
+        assert_eq!(max_spawns(4, 9), 8);
+        assert!((1..20).all(|depth| max_spawns(4, depth) <= max_spawns(4, depth + 1)));
+        // Depths above the first level are treated as the first one.
+        assert_eq!(max_spawns(4, 0), 4);
+    }
 }
@@ -0,0 +1,25 @@
+use crate::map::Tile;
+
+use super::{BuildFeatures, MapBuilder, MetaMapBuilder};
+
+/// Places the stairs up on the starting position, so that the player arrives on them.
+pub struct UpStairsAtStart;
+
+impl MetaMapBuilder for UpStairsAtStart {
+    fn build_map(&mut self, build_data: &mut MapBuilder) {
+        let starting_position = build_data
+            .starting_position
+            .expect("UpStairsAtStart needs a starting position");
+        let idx = build_data
+            .map
+            .xy_idx(starting_position.x, starting_position.y);
+        build_data.map.tiles[idx] = Tile::UpStairs;
+    }
+
+    fn requirements(&self) -> BuildFeatures {
+        BuildFeatures {
+            starting_position: true,
+            ..BuildFeatures::default()
+        }
+    }
+}
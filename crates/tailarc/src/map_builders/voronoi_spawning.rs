@@ -0,0 +1,78 @@
+use std::collections::BTreeMap;
+
+use rand::Rng;
+
+use crate::components::Position;
+use crate::map::Tile;
+
+use super::spawner::{max_spawns, spawn_area};
+use super::{BuildFeatures, MapBuilder, MetaMapBuilder};
+
+/// Number of areas that the map is split into.
+const NUM_AREAS: usize = 16;
+
+/// Spawns entities in maps without rooms, such as caves.
+///
+/// The floor is split into areas with a Voronoi diagram: random seed points are picked and every
+/// floor tile belongs to the area of the closest seed. Each area is then filled like a room, except
+/// the area of the starting position so that the player is not surrounded when they arrive.
+pub struct VoronoiSpawning;
+
+impl MetaMapBuilder for VoronoiSpawning {
+    fn build_map(&mut self, build_data: &mut MapBuilder) {
+        let starting_position = build_data
+            .starting_position
+            .expect("VoronoiSpawning needs a starting position");
+        let map = &build_data.map;
+
+        let seeds: Vec<(i32, i32)> = (0..NUM_AREAS)
+            .map(|_| {
+                (
+                    build_data.rng.gen_range(1..map.width - 1) as i32,
+                    build_data.rng.gen_range(1..map.height - 1) as i32,
+                )
+            })
+            .collect();
+        let closest_seed = |x: u32, y: u32| {
+            (0..seeds.len())
+                .min_by_key(|&i| (seeds[i].0 - x as i32).abs() + (seeds[i].1 - y as i32).abs())
+                .expect("there is at least one area")
+        };
+
+        // A `BTreeMap` keeps the order of the areas deterministic.
+        let mut areas: BTreeMap<usize, Vec<Position>> = BTreeMap::new();
+        for (idx, _) in map
+            .tiles
+            .iter()
+            .enumerate()
+            .filter(|&(_, &tile)| tile == Tile::Floor)
+        {
+            let x = idx as u32 % map.width;
+            let y = idx as u32 / map.width;
+            areas
+                .entry(closest_seed(x, y))
+                .or_default()
+                .push(Position { x, y });
+        }
+        areas.remove(&closest_seed(starting_position.x, starting_position.y));
+
+        let depth = build_data.map.depth;
+        for area in areas.values() {
+            spawn_area(
+                &mut build_data.spawn_list,
+                area,
+                depth,
+                max_spawns(3, depth),
+                max_spawns(4, depth),
+                &mut build_data.rng,
+            );
+        }
+    }
+
+    fn requirements(&self) -> BuildFeatures {
+        BuildFeatures {
+            starting_position: true,
+            ..BuildFeatures::default()
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Records of the characters that died.
+//!
+//! When the player dies, a plain-text morgue file describing the character and the game is written
+//! next to the save files.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use bevy_ecs::prelude::*;
+
+use crate::components::{CanSufferDamage, CombatStats, EntityName, Equipped, Owned, Player};
+use crate::gamelog::GameLog;
+use crate::highscores::score;
+use crate::map::{Map, Tile};
+use crate::rng::GameRng;
+use crate::save::{data_dir, format_timestamp, timestamp_now};
+use crate::TurnCounter;
+
+/// Number of [`GameLog`] lines written to the morgue file.
+pub const MORGUE_LOG_LINES: usize = 20;
+
+/// A resource counting the monsters killed during the game by name.
+#[derive(Debug, Default, Clone)]
+pub struct KillCounts {
+    pub kills: BTreeMap<String, u32>,
+}
+
+impl KillCounts {
+    pub fn add(&mut self, name: &str) {
+        *self.kills.entry(name.to_string()).or_insert(0) += 1;
+    }
+
+    /// Returns the total number of monsters killed.
+    pub fn total(&self) -> u32 {
+        self.kills.values().sum()
+    }
+}
+
+/// A resource containing the morgue of the last character that died. Displayed in the death
+/// screen.
+#[derive(Debug, Default)]
+pub struct Morgue {
+    /// Summary of the character shown in the death screen.
+    pub summary: Vec<String>,
+    /// Path of the morgue file or `None` if it could not be written.
+    pub path: Option<PathBuf>,
+}
+
+/// Returns the path of the morgue file for a character that died at the given time.
+pub fn morgue_path(timestamp: u64) -> PathBuf {
+    [
+        data_dir().as_path(),
+        Path::new(&format!("morgue-{}.txt", timestamp)),
+    ]
+    .into_iter()
+    .collect()
+}
+
+/// Writes a morgue file.
+pub fn write_morgue(timestamp: u64, text: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(data_dir())?;
+    let path = morgue_path(timestamp);
+    fs::write(&path, text)?;
+    Ok(path)
+}
+
+/// Returns an ASCII snapshot of the tiles of the map that have been revealed, cropped to the
+/// revealed area.
+pub fn revealed_map_ascii(map: &Map) -> String {
+    let revealed = |x: u32, y: u32| map.revealed_tiles[map.xy_idx(x, y)];
+    let rows: Vec<u32> = (0..map.height)
+        .filter(|&y| (0..map.width).any(|x| revealed(x, y)))
+        .collect();
+    let columns: Vec<u32> = (0..map.width)
+        .filter(|&x| (0..map.height).any(|y| revealed(x, y)))
+        .collect();
+    let (min_x, max_x) = match (columns.first(), columns.last()) {
+        (Some(&min_x), Some(&max_x)) => (min_x, max_x),
+        _ => return String::new(),
+    };
+
+    let mut ascii = String::new();
+    for y in rows {
+        let line: String = (min_x..=max_x)
+            .map(|x| match map.tiles[map.xy_idx(x, y)] {
+                _ if !revealed(x, y) => ' ',
+                Tile::Wall => '#',
+                Tile::Floor => '.',
+                Tile::DownStairs => '>',
+                Tile::UpStairs => '<',
+            })
+            .collect();
+        ascii.push_str(line.trim_end());
+        ascii.push('\n');
+    }
+    ascii
+}
+
+/// Writes the morgue file of the player that just died and fills the [`Morgue`] resource for the
+/// death screen.
+pub fn write_morgue_system(
+    mut morgue: ResMut<Morgue>,
+    map: Res<Map>,
+    game_log: Res<GameLog>,
+    kill_counts: Res<KillCounts>,
+    turn_counter: Res<TurnCounter>,
+    rng: Res<GameRng>,
+    player: Query<(Entity, &EntityName, &CombatStats, &CanSufferDamage), With<Player>>,
+    equipped: Query<(&EntityName, &Equipped)>,
+    owned: Query<(&EntityName, &Owned)>,
+) {
+    let (player_entity, name, stats, can_suffer_damage) = player.single();
+    let timestamp = timestamp_now();
+
+    let summary = vec![
+        match &can_suffer_damage.last_attacker {
+            Some(attacker) => format!(
+                "{} was killed by {} on depth {} after {} turns.",
+                name.name, attacker, map.depth, turn_counter.turn
+            ),
+            None => format!(
+                "{} died on depth {} after {} turns.",
+                name.name, map.depth, turn_counter.turn
+            ),
+        },
+        format!(
+            "HP: {}/{}  Power: {}  Defense: {}",
+            stats.hp, stats.max_hp, stats.power, stats.defense
+        ),
+        format!("Monsters killed: {}", kill_counts.total()),
+        format!(
+            "Score: {}",
+            score(map.depth, kill_counts.total(), turn_counter.turn)
+        ),
+    ];
+
+    // Writing to a `String` cannot fail.
+    let mut text = String::new();
+    writeln!(text, "Tailarc morgue file, {}", format_timestamp(timestamp)).unwrap();
+    writeln!(text, "Seed: {}", rng.seed()).unwrap();
+    writeln!(text).unwrap();
+    for line in &summary {
+        writeln!(text, "{}", line).unwrap();
+    }
+
+    writeln!(text, "\nEquipment:").unwrap();
+    let mut equipment: Vec<_> = equipped
+        .iter()
+        .filter(|(_, equipped)| equipped.by == player_entity)
+        .map(|(name, equipped)| format!("  {:?}: {}", equipped.slot, name.name))
+        .collect();
+    equipment.sort();
+    if equipment.is_empty() {
+        writeln!(text, "  (nothing)").unwrap();
+    }
+    for line in equipment {
+        writeln!(text, "{}", line).unwrap();
+    }
+
+    writeln!(text, "\nInventory:").unwrap();
+    let mut inventory: Vec<_> = owned
+        .iter()
+        .filter(|(_, owned)| owned.owner == player_entity)
+        .map(|(name, _)| format!("  {}", name.name))
+        .collect();
+    inventory.sort();
+    if inventory.is_empty() {
+        writeln!(text, "  (nothing)").unwrap();
+    }
+    for line in inventory {
+        writeln!(text, "{}", line).unwrap();
+    }
+
+    writeln!(text, "\nKills:").unwrap();
+    if kill_counts.kills.is_empty() {
+        writeln!(text, "  (none)").unwrap();
+    }
+    for (name, count) in &kill_counts.kills {
+        writeln!(text, "  {:>4} {}", count, name).unwrap();
+    }
+
+    writeln!(text, "\nLast messages:").unwrap();
+    let entries = game_log.entries.lock().unwrap();
+    for entry in &entries[entries.len().saturating_sub(MORGUE_LOG_LINES)..] {
+        writeln!(text, "  {}", entry).unwrap();
+    }
+
+    writeln!(text, "\nMap of depth {}:", map.depth).unwrap();
+    text.push_str(&revealed_map_ascii(&map));
+
+    morgue.summary = summary;
+    morgue.path = match write_morgue(timestamp, &text) {
+        Ok(path) => Some(path),
+        Err(err) => {
+            tracing::error!("could not write morgue file: {}", err);
+            None
+        }
+    };
+}
@@ -7,6 +7,8 @@ pub struct ItemRaw {
     pub name: String,
     pub renderable: Option<Renderable>,
     pub consumable: Option<Consumable>,
+    /// Range of the items that are used on a target.
+    pub ranged: Option<i32>,
     pub equippable: Option<Equippable>,
     pub stats: Option<ItemStats>,
 }
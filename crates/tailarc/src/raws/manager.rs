@@ -16,10 +16,7 @@ pub struct RawManager {
 impl RawManager {
     pub fn new() -> Self {
         Self {
-            raws: Raws {
-                items: Vec::new(),
-                mobs: Vec::new(),
-            },
+            raws: Raws::default(),
             item_index: HashMap::new(),
             mob_index: HashMap::new(),
         }
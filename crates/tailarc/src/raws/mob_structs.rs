@@ -1,6 +1,6 @@
 use serde::Deserialize;
 
-use crate::components::{CombatStats, Renderable};
+use crate::components::{Attributes, CombatStats, MovementMode, Renderable};
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct MobRaw {
@@ -8,5 +8,14 @@ pub struct MobRaw {
     pub renderable: Renderable,
     pub blocks_tile: bool,
     pub vision_range: i32,
+    /// Mobs chase the player if not specified.
+    #[serde(default)]
+    pub movement: MovementMode,
+    /// Average attributes if not specified.
+    #[serde(default)]
+    pub attributes: Attributes,
+    /// Energy gained per turn. Mobs have the normal speed if not specified.
+    pub speed: Option<i32>,
+    /// The stats of the mob with average attributes.
     pub stats: CombatStats,
 }
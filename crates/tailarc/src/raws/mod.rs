@@ -3,33 +3,233 @@
 mod item_structs;
 mod manager;
 mod mob_structs;
+mod spawn_table_structs;
+mod validate;
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 use bevy_ecs::prelude::Commands;
 use include_dir::{include_dir, Dir};
 use serde::Deserialize;
 
 use crate::components::{
-    BlocksTile, CanSufferDamage, EntityName, Mob, MobBundle, Position, Viewshed,
+    BlocksTile, CanSufferDamage, Energy, EntityName, Mob, MobBundle, Movement, Position, Ranged,
+    Viewshed, NORMAL_SPEED,
 };
+use crate::save::data_dir;
 
 use self::item_structs::Consumable;
 pub use self::manager::RAW_MANAGER;
+pub use self::spawn_table_structs::SpawnTableEntry;
+pub use self::validate::{validate_raws, RawError};
 
 /// The `/static` directory.
 pub static STATIC: Dir = include_dir!("$CARGO_MANIFEST_DIR/../../static");
 
-#[derive(Debug, Deserialize, Clone)]
+/// The content of a raw file. Every section is optional so that mods only contain what they add or
+/// change.
+#[derive(Debug, Default, Deserialize, Clone)]
 pub struct Raws {
+    #[serde(default)]
     pub items: Vec<item_structs::ItemRaw>,
+    #[serde(default)]
     pub mobs: Vec<mob_structs::MobRaw>,
+    #[serde(default)]
+    pub spawn_table: Vec<SpawnTableEntry>,
+}
+
+impl Raws {
+    /// Merges the raws of a file loaded after these ones.
+    ///
+    /// An item, mob or spawn table entry replaces the one with the same name (an item also
+    /// replaces a mob and vice versa). New ones are appended.
+    pub fn merge(&mut self, other: Raws) {
+        for item in other.items {
+            self.mobs.retain(|mob| mob.name != item.name);
+            match self.items.iter_mut().find(|i| i.name == item.name) {
+                Some(existing) => *existing = item,
+                None => self.items.push(item),
+            }
+        }
+        for mob in other.mobs {
+            self.items.retain(|item| item.name != mob.name);
+            match self.mobs.iter_mut().find(|m| m.name == mob.name) {
+                Some(existing) => *existing = mob,
+                None => self.mobs.push(mob),
+            }
+        }
+        for entry in other.spawn_table {
+            match self.spawn_table.iter_mut().find(|e| e.name == entry.name) {
+                Some(existing) => *existing = entry,
+                None => self.spawn_table.push(entry),
+            }
+        }
+    }
+
+    /// Returns the names of all the items and mobs.
+    pub fn names(&self) -> HashSet<String> {
+        self.items
+            .iter()
+            .map(|item| item.name.clone())
+            .chain(self.mobs.iter().map(|mob| mob.name.clone()))
+            .collect()
+    }
+}
+
+/// A resource containing the directory given with `--raws`, so that the raws can be reloaded.
+#[derive(Debug, Clone, Default)]
+pub struct RawsDir(pub Option<PathBuf>);
+
+/// Returns the directory containing the raw files of the mods installed by the player.
+pub fn mods_dir() -> PathBuf {
+    [data_dir().as_path(), Path::new("mods")]
+        .into_iter()
+        .collect()
+}
+
+/// Parses and validates a raw file.
+///
+/// `known_names` are the names defined by the files loaded before this one. Returns all the errors
+/// found in the file if it is not valid.
+pub fn parse_raws(
+    file: &str,
+    data: &[u8],
+    known_names: &HashSet<String>,
+) -> Result<Raws, Vec<RawError>> {
+    let error = |message: String| RawError {
+        file: file.to_string(),
+        path: "$".to_string(),
+        message,
+    };
+
+    let json: serde_json::Value = serde_json::from_slice(data)
+        .map_err(|err| vec![error(format!("invalid JSON: {}", err))])?;
+    let errors = validate_raws(file, &json, known_names);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    // The validation does not check everything, e.g. missing fields.
+    serde_json::from_value(json).map_err(|err| vec![error(err.to_string())])
 }
 
-/// Loads the raws from the `/static/spawns.json` file into memory.
-pub fn load_spawns() {
-    let json = STATIC.get_file("spawns.json").unwrap().contents();
-    let raws: Raws = serde_json::from_slice(json).expect("could not parse spawns.json");
+/// Reads the `.json` files of a directory, sorted by name.
+fn read_raw_dir(dir: &Path) -> Result<Vec<(String, Vec<u8>)>, RawError> {
+    let error = |file: &Path, err: std::io::Error| RawError {
+        file: file.display().to_string(),
+        path: "$".to_string(),
+        message: err.to_string(),
+    };
+
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|err| error(dir, err))? {
+        let path = entry.map_err(|err| error(dir, err))?.path();
+        if path.extension() == Some("json".as_ref()) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let data = fs::read(&path).map_err(|err| error(&path, err))?;
+            Ok((path.display().to_string(), data))
+        })
+        .collect()
+}
+
+/// Returns the built-in `/static/spawns.json` file.
+///
+/// Debug builds read it from the source tree instead of the copy embedded at compile time, so that
+/// reloading the raws picks up the changes made to it. The embedded copy is used when the source
+/// tree is not there anymore, e.g. when the binary was moved to another machine.
+fn read_builtin_raws() -> Result<(String, Vec<u8>), RawError> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../static/spawns.json");
+    if cfg!(debug_assertions) && cfg!(not(target_arch = "wasm32")) && path.exists() {
+        let data = fs::read(&path).map_err(|err| RawError {
+            file: path.display().to_string(),
+            path: "$".to_string(),
+            message: err.to_string(),
+        })?;
+        return Ok((path.display().to_string(), data));
+    }
+
+    Ok((
+        "spawns.json".to_string(),
+        STATIC.get_file("spawns.json").unwrap().contents().to_vec(),
+    ))
+}
+
+/// Loads the raws into memory.
+///
+/// The raw files are loaded in this order, each file overriding the items, mobs and spawn table
+/// entries with the same name of the previous ones:
+/// 1. the built-in `/static/spawns.json` file (read from the source tree in debug builds),
+/// 2. the files of the [mods directory](mods_dir), by file name,
+/// 3. the files of `raws_dir` (given with `--raws <dir>`), by file name.
+///
+/// Nothing is loaded if any file is invalid.
+pub fn load_raws(raws_dir: Option<&Path>) -> Result<(), Vec<RawError>> {
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    match read_builtin_raws() {
+        Ok(file) => files.push(file),
+        Err(err) => errors.push(err),
+    }
+    // There is no file system on the web.
+    if cfg!(not(target_arch = "wasm32")) {
+        let mods_dir = mods_dir();
+        if mods_dir.is_dir() {
+            match read_raw_dir(&mods_dir) {
+                Ok(mod_files) => files.extend(mod_files),
+                Err(err) => errors.push(err),
+            }
+        }
+    }
+    if let Some(raws_dir) = raws_dir {
+        match read_raw_dir(raws_dir) {
+            Ok(dir_files) => files.extend(dir_files),
+            Err(err) => errors.push(err),
+        }
+    }
+
+    let mut raws = Raws::default();
+    for (file, data) in files {
+        match parse_raws(&file, &data, &raws.names()) {
+            Ok(file_raws) => {
+                tracing::info!("loaded raws from {}", file);
+                raws.merge(file_raws);
+            }
+            Err(file_errors) => errors.extend(file_errors),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
 
     RAW_MANAGER.write().load(raws);
+    Ok(())
+}
+
+/// Checks the raw files and prints the errors found, for the `--check-raws` mode of the game.
+///
+/// Returns `true` if the raws are valid.
+pub fn check_raws(raws_dir: Option<&Path>) -> bool {
+    match load_raws(raws_dir) {
+        Ok(()) => {
+            println!("Raws OK");
+            true
+        }
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("{}", error);
+            }
+            eprintln!("{} error(s) found", errors.len());
+            false
+        }
+    }
 }
 
 pub fn get_item(name: &str) -> Option<item_structs::ItemRaw> {
@@ -73,6 +273,10 @@ pub fn try_spawn_named_item(commands: &mut Commands, name: &str, pos: SpawnType)
     if let Some(Consumable { effects }) = &item.consumable {
         e.insert(*effects);
     }
+    // Ranged.
+    if let Some(range) = item.ranged {
+        e.insert(Ranged { range });
+    }
     // Equippable.
     if let Some(equippable) = &item.equippable {
         e.insert(*equippable);
@@ -106,8 +310,11 @@ pub fn try_spawn_named_mob(commands: &mut Commands, name: &str, position: SpawnT
         position,
         renderable: mob.renderable,
         viewshed: Viewshed::new(mob.vision_range),
+        movement: Movement::new(mob.movement),
         blocks_tile: BlocksTile,
-        combat_stats: mob.stats,
+        combat_stats: mob.stats.with_attributes(&mob.attributes),
+        attributes: mob.attributes,
+        energy: Energy::new(mob.speed.unwrap_or(NORMAL_SPEED)),
         can_suffer_damage: CanSufferDamage::default(),
     });
 
@@ -127,3 +334,93 @@ pub fn spawn_named_entity(commands: &mut Commands, name: &str, pos: SpawnType) {
         panic!("could not spawn entity with name {}", name);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn item(name: &str) -> Value {
+        json!({"name": name, "consumable": {"effects": {"heal": 10}}})
+    }
+
+    fn mob(name: &str, hp: i32) -> Value {
+        json!({
+            "name": name,
+            "renderable": {"glyph": "g", "fg": "#ff0000", "bg": "#000000", "z_index": 2},
+            "blocks_tile": true,
+            "vision_range": 8,
+            "stats": {"max_hp": hp, "hp": hp, "defense": 1, "power": 4},
+        })
+    }
+
+    fn entry(name: &str, weight: u32) -> Value {
+        json!({"name": name, "weight": weight, "min_depth": 1, "max_depth": 10})
+    }
+
+    fn parse(json: Value, known_names: &HashSet<String>) -> Result<Raws, Vec<RawError>> {
+        parse_raws(
+            "test.json",
+            &serde_json::to_vec(&json).unwrap(),
+            known_names,
+        )
+    }
+
+    #[test]
+    fn merge_later_files() {
+        let mut raws = parse(
+            json!({
+                "items": [item("Health Potion")],
+                "mobs": [mob("Goblin", 16), mob("Orc", 20)],
+                "spawn_table": [entry("Goblin", 10), entry("Orc", 5)],
+            }),
+            &HashSet::new(),
+        )
+        .unwrap();
+        let mod_raws = parse(
+            json!({
+                "items": [item("Goblin")],
+                "mobs": [mob("Orc", 30), mob("Kobold", 8)],
+                "spawn_table": [entry("Goblin", 1), entry("Kobold", 3)],
+            }),
+            &raws.names(),
+        )
+        .unwrap();
+        raws.merge(mod_raws);
+
+        let items: Vec<_> = raws.items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(items, vec!["Health Potion", "Goblin"]);
+        let mobs: Vec<_> = raws
+            .mobs
+            .iter()
+            .map(|mob| (mob.name.as_str(), mob.stats.max_hp))
+            .collect();
+        assert_eq!(mobs, vec![("Orc", 30), ("Kobold", 8)]);
+        let spawn_table: Vec<_> = raws
+            .spawn_table
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.weight))
+            .collect();
+        assert_eq!(spawn_table, vec![("Goblin", 1), ("Orc", 5), ("Kobold", 3)]);
+
+        // A mob also replaces an item.
+        raws.merge(parse(json!({"mobs": [mob("Health Potion", 1)]}), &HashSet::new()).unwrap());
+        let items: Vec<_> = raws.items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(items, vec!["Goblin"]);
+        assert_eq!(raws.mobs.last().unwrap().name, "Health Potion");
+    }
+
+    #[test]
+    fn spawn_table_refers_to_earlier_files() {
+        let raws = parse(json!({"mobs": [mob("Goblin", 16)]}), &HashSet::new()).unwrap();
+        let mod_json = json!({"spawn_table": [entry("Goblin", 10)]});
+
+        let mod_raws = parse(mod_json.clone(), &raws.names()).unwrap();
+        assert_eq!(mod_raws.spawn_table[0].name, "Goblin");
+
+        let errors = parse(mod_json, &HashSet::new()).unwrap_err();
+        let paths: Vec<_> = errors.iter().map(|error| error.path.as_str()).collect();
+        assert_eq!(paths, vec!["$.spawn_table[0].name"]);
+    }
+}
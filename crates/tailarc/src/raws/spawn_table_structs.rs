@@ -0,0 +1,14 @@
+use serde::Deserialize;
+
+/// An entry of the spawn table, which decides what appears in the levels.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SpawnTableEntry {
+    /// Name of the mob or item.
+    pub name: String,
+    /// How likely the entry is to be picked compared to the other entries.
+    pub weight: u32,
+    /// First depth at which the entry can appear.
+    pub min_depth: i32,
+    /// Last depth at which the entry can appear.
+    pub max_depth: i32,
+}
@@ -0,0 +1,389 @@
+//! Validation of raw files.
+//!
+//! The raws are checked as JSON before being deserialized so that errors point at the exact value
+//! that is wrong, and so that every error of a file is reported at once.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use bracket_lib::prelude::{to_cp437, RGB};
+use serde_json::Value;
+
+use crate::components::Attributes;
+
+/// Fields of the `attributes` of mobs.
+const ATTRIBUTES: &[&str] = &["might", "fitness", "quickness", "intelligence"];
+
+/// Values of the `movement` field of mobs.
+const MOVEMENT_MODES: &[&str] = &["static", "random", "wandering", "chase"];
+
+/// Values of the `kind` field of status effects.
+const STATUS_EFFECT_KINDS: &[&str] = &["confusion", "poison", "regeneration", "haste", "slow"];
+
+/// An error found in a raw file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawError {
+    /// Name of the raw file.
+    pub file: String,
+    /// JSON path of the invalid value (e.g. `$.mobs[0].stats.hp`).
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for RawError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.file, self.path, self.message)
+    }
+}
+
+/// Checks the raws of a file. Returns all the errors found.
+///
+/// `known_names` are the names of the items and mobs defined by the files loaded before this one,
+/// which the spawn table can refer to.
+pub fn validate_raws(file: &str, json: &Value, known_names: &HashSet<String>) -> Vec<RawError> {
+    let mut validator = Validator {
+        file,
+        errors: Vec::new(),
+        names: HashMap::new(),
+        known_names,
+    };
+
+    if !json.is_object() {
+        validator.error("$", "expected an object");
+        return validator.errors;
+    }
+    for (i, item) in validator.array(json, "items").iter().enumerate() {
+        validator.item(&format!("$.items[{}]", i), item);
+    }
+    for (i, mob) in validator.array(json, "mobs").iter().enumerate() {
+        validator.mob(&format!("$.mobs[{}]", i), mob);
+    }
+    // The spawn table is checked last so that all the names are known.
+    for (i, entry) in validator.array(json, "spawn_table").iter().enumerate() {
+        validator.spawn_table_entry(&format!("$.spawn_table[{}]", i), entry);
+    }
+
+    validator.errors
+}
+
+struct Validator<'a> {
+    file: &'a str,
+    errors: Vec<RawError>,
+    /// Path of the first definition of every item and mob name in this file.
+    names: HashMap<String, String>,
+    known_names: &'a HashSet<String>,
+}
+
+impl Validator<'_> {
+    fn error(&mut self, path: &str, message: impl Into<String>) {
+        self.errors.push(RawError {
+            file: self.file.to_string(),
+            path: path.to_string(),
+            message: message.into(),
+        });
+    }
+
+    /// Returns the array at `key` in `json`, or an empty slice if it is missing. Reports an error
+    /// if it is not an array.
+    fn array<'v>(&mut self, json: &'v Value, key: &str) -> &'v [Value] {
+        match json.get(key).map(Value::as_array) {
+            Some(Some(array)) => array,
+            Some(None) => {
+                self.error(&format!("$.{}", key), "expected an array");
+                &[]
+            }
+            None => &[],
+        }
+    }
+
+    /// Checks the name of an item or mob and records it.
+    fn name(&mut self, path: &str, json: &Value) {
+        let path = format!("{}.name", path);
+        match json.get("name").and_then(Value::as_str) {
+            Some(name) => {
+                if let Some(first) = self.names.get(name) {
+                    let message = format!("duplicate name {:?}, first defined at {}", name, first);
+                    self.error(&path, message);
+                } else {
+                    self.names.insert(name.to_string(), path);
+                }
+            }
+            None => self.error(&path, "expected a string"),
+        }
+    }
+
+    fn renderable(&mut self, path: &str, json: &Value) {
+        match json.get("glyph").and_then(Value::as_str) {
+            Some(glyph) => {
+                let mut chars = glyph.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => {
+                        // Characters that are not in CP437 are all mapped to `0`.
+                        if c != '\0' && to_cp437(c) == 0 {
+                            let message = format!("glyph {:?} is not a CP437 character", c);
+                            self.error(&format!("{}.glyph", path), message);
+                        }
+                    }
+                    _ => self.error(&format!("{}.glyph", path), "expected a single character"),
+                }
+            }
+            None => self.error(&format!("{}.glyph", path), "expected a string"),
+        }
+
+        for key in ["fg", "bg"] {
+            let color_path = format!("{}.{}", path, key);
+            match json.get(key).and_then(Value::as_str) {
+                Some(color) => {
+                    if RGB::from_hex(color).is_err() {
+                        let message = format!("invalid hex color {:?}", color);
+                        self.error(&color_path, message);
+                    }
+                }
+                None => self.error(&color_path, "expected a hex color string"),
+            }
+        }
+    }
+
+    fn item(&mut self, path: &str, json: &Value) {
+        self.name(path, json);
+        if matches!(json.get("ranged").and_then(Value::as_i64), Some(range) if range <= 0) {
+            self.error(&format!("{}.ranged", path), "must be positive");
+        }
+        if let Some(renderable) = json.get("renderable") {
+            self.renderable(&format!("{}.renderable", path), renderable);
+        }
+        for effect in ["heal", "damage", "area_of_effect"] {
+            let value = json
+                .pointer(&format!("/consumable/effects/{}", effect))
+                .and_then(Value::as_i64);
+            if matches!(value, Some(value) if value <= 0) {
+                let effect_path = format!("{}.consumable.effects.{}", path, effect);
+                self.error(&effect_path, "must be positive");
+            }
+        }
+        if let Some(status_effect) = json.pointer("/consumable/effects/status_effect") {
+            let path = format!("{}.consumable.effects.status_effect", path);
+            self.status_effect(&path, status_effect);
+        }
+    }
+
+    fn status_effect(&mut self, path: &str, json: &Value) {
+        let kind = json.get("kind").and_then(Value::as_str);
+        if !matches!(kind, Some(kind) if STATUS_EFFECT_KINDS.contains(&kind)) {
+            let message = format!("expected one of {}", STATUS_EFFECT_KINDS.join(", "));
+            self.error(&format!("{}.kind", path), message);
+        }
+        let amount = match kind {
+            Some("poison") => Some("damage"),
+            Some("regeneration") => Some("heal"),
+            _ => None,
+        };
+        for key in std::iter::once("turns").chain(amount) {
+            if !matches!(json.get(key).and_then(Value::as_i64), Some(value) if value > 0) {
+                self.error(&format!("{}.{}", path, key), "must be a positive integer");
+            }
+        }
+    }
+
+    fn mob(&mut self, path: &str, json: &Value) {
+        self.name(path, json);
+        match json.get("renderable") {
+            Some(renderable) => self.renderable(&format!("{}.renderable", path), renderable),
+            None => self.error(&format!("{}.renderable", path), "missing field"),
+        }
+        if let Some(movement) = json.get("movement") {
+            if !MOVEMENT_MODES.iter().any(|mode| movement == mode) {
+                let message = format!("expected one of {}", MOVEMENT_MODES.join(", "));
+                self.error(&format!("{}.movement", path), message);
+            }
+        }
+        for key in ["vision_range", "speed"] {
+            if matches!(json.get(key).and_then(Value::as_i64), Some(value) if value <= 0) {
+                self.error(&format!("{}.{}", path, key), "must be positive");
+            }
+        }
+
+        if let Some(attributes) = json.get("attributes").and_then(Value::as_object) {
+            for (key, value) in attributes {
+                let attribute_path = format!("{}.attributes.{}", path, key);
+                if !ATTRIBUTES.contains(&key.as_str()) {
+                    let message = format!("expected one of {}", ATTRIBUTES.join(", "));
+                    self.error(&attribute_path, message);
+                } else if matches!(value.as_i64(), Some(value) if value < 1) {
+                    self.error(&attribute_path, "must be positive");
+                }
+            }
+        }
+
+        let stats_path = format!("{}.stats", path);
+        let stat = |key: &str| {
+            json.get("stats")
+                .and_then(|s| s.get(key))
+                .and_then(Value::as_i64)
+        };
+        if let Some(max_hp) = stat("max_hp") {
+            if max_hp <= 0 {
+                self.error(&format!("{}.max_hp", stats_path), "must be positive");
+            }
+            if let Some(hp) = stat("hp") {
+                if hp <= 0 || hp > max_hp {
+                    let message = format!("must be between 1 and max_hp ({})", max_hp);
+                    self.error(&format!("{}.hp", stats_path), message);
+                }
+            }
+
+            // The fitness of the mob changes its hp, it must not spawn dead.
+            if let Some(fitness) = json.pointer("/attributes/fitness").and_then(Value::as_i64) {
+                let attributes = Attributes {
+                    fitness: fitness as i32,
+                    ..Attributes::default()
+                };
+                let hp = stat("hp").unwrap_or(max_hp) + attributes.max_hp_bonus() as i64;
+                if hp <= 0 {
+                    let message = format!("leaves the mob with {} hp", hp);
+                    self.error(&format!("{}.attributes.fitness", path), message);
+                }
+            }
+        }
+        for key in ["defense", "power"] {
+            if matches!(stat(key), Some(value) if value < 0) {
+                self.error(&format!("{}.{}", stats_path, key), "must not be negative");
+            }
+        }
+    }
+
+    fn spawn_table_entry(&mut self, path: &str, json: &Value) {
+        match json.get("name").and_then(Value::as_str) {
+            Some(name) => {
+                if !self.names.contains_key(name) && !self.known_names.contains(name) {
+                    let message = format!("unknown item or mob {:?}", name);
+                    self.error(&format!("{}.name", path), message);
+                }
+            }
+            None => self.error(&format!("{}.name", path), "expected a string"),
+        }
+        if json.get("weight").and_then(Value::as_u64) == Some(0) {
+            self.error(&format!("{}.weight", path), "must be positive");
+        }
+
+        let min_depth = json.get("min_depth").and_then(Value::as_i64);
+        let max_depth = json.get("max_depth").and_then(Value::as_i64);
+        if matches!(min_depth, Some(min_depth) if min_depth < 1) {
+            self.error(&format!("{}.min_depth", path), "must be at least 1");
+        }
+        if let (Some(min_depth), Some(max_depth)) = (min_depth, max_depth) {
+            if min_depth > max_depth {
+                let message = format!("must not be less than min_depth ({})", min_depth);
+                self.error(&format!("{}.max_depth", path), message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::raws::STATIC;
+
+    /// Returns valid raws, to be broken by the tests.
+    fn raws() -> Value {
+        json!({
+            "items": [{
+                "name": "Health Potion",
+                "renderable": {"glyph": "!", "fg": "#ff00ff", "bg": "#000000", "z_index": 1},
+                "consumable": {"effects": {"heal": 10}},
+            }],
+            "mobs": [{
+                "name": "Goblin",
+                "renderable": {"glyph": "g", "fg": "#ff0000", "bg": "#000000", "z_index": 2},
+                "blocks_tile": true,
+                "vision_range": 8,
+                "stats": {"max_hp": 16, "hp": 16, "defense": 1, "power": 4},
+            }],
+            "spawn_table": [{"name": "Goblin", "weight": 10, "min_depth": 1, "max_depth": 100}],
+        })
+    }
+
+    /// Returns the paths of the errors found in the raws.
+    fn error_paths(json: &Value) -> Vec<String> {
+        validate_raws("test.json", json, &HashSet::new())
+            .into_iter()
+            .map(|error| error.path)
+            .collect()
+    }
+
+    #[test]
+    fn valid_raws() {
+        assert!(error_paths(&raws()).is_empty());
+    }
+
+    #[test]
+    fn shipped_raws_are_valid() {
+        let data = STATIC.get_file("spawns.json").unwrap().contents();
+        let json = serde_json::from_slice(data).unwrap();
+        assert_eq!(
+            validate_raws("spawns.json", &json, &HashSet::new()),
+            Vec::new()
+        );
+    }
+
+    #[test]
+    fn duplicate_name() {
+        let mut json = raws();
+        json["mobs"][0]["name"] = json!("Health Potion");
+        json["spawn_table"][0]["name"] = json!("Health Potion");
+        assert_eq!(error_paths(&json), vec!["$.mobs[0].name"]);
+    }
+
+    #[test]
+    fn unknown_spawn_table_name() {
+        let mut json = raws();
+        json["spawn_table"][0]["name"] = json!("Dragon");
+        assert_eq!(error_paths(&json), vec!["$.spawn_table[0].name"]);
+    }
+
+    #[test]
+    fn invalid_hex_color() {
+        let mut json = raws();
+        json["items"][0]["renderable"]["fg"] = json!("#ff00zz");
+        assert_eq!(error_paths(&json), vec!["$.items[0].renderable.fg"]);
+    }
+
+    #[test]
+    fn glyph_not_in_cp437() {
+        let mut json = raws();
+        json["mobs"][0]["renderable"]["glyph"] = json!("€");
+        assert_eq!(error_paths(&json), vec!["$.mobs[0].renderable.glyph"]);
+    }
+
+    #[test]
+    fn hp_above_max_hp() {
+        let mut json = raws();
+        json["mobs"][0]["stats"]["hp"] = json!(17);
+        assert_eq!(error_paths(&json), vec!["$.mobs[0].stats.hp"]);
+    }
+
+    #[test]
+    fn fitness_leaves_no_hp() {
+        let mut json = raws();
+        json["mobs"][0]["attributes"] = json!({"fitness": 3});
+        assert_eq!(error_paths(&json), vec!["$.mobs[0].attributes.fitness"]);
+    }
+
+    #[test]
+    fn min_depth_above_max_depth() {
+        let mut json = raws();
+        json["spawn_table"][0]["min_depth"] = json!(5);
+        json["spawn_table"][0]["max_depth"] = json!(4);
+        assert_eq!(error_paths(&json), vec!["$.spawn_table[0].max_depth"]);
+    }
+
+    #[test]
+    fn zero_weight() {
+        let mut json = raws();
+        json["spawn_table"][0]["weight"] = json!(0);
+        assert_eq!(error_paths(&json), vec!["$.spawn_table[0].weight"]);
+    }
+}
@@ -4,8 +4,10 @@ use bevy_ecs::prelude::*;
 use bracket_lib::prelude::*;
 
 use crate::components::{Player, Position, Renderable};
+use crate::highscores::{HighScores, MAX_HIGH_SCORES};
 use crate::map::{Map, Tile};
-use crate::{CONSOLE_HEIGHT, CONSOLE_WIDTH, DEBUG_MAP_XRAY};
+use crate::save::{format_timestamp, ActiveSlot, Run, SaveSlots, SAVE_SLOTS};
+use crate::{RunState, CONSOLE_HEIGHT, CONSOLE_WIDTH, DEBUG_MAP_XRAY};
 
 /// Returns true if the given tile has been revealed and is a wall.
 /// Returns false otherwise.
@@ -57,6 +59,19 @@ fn wall_glyph(map: &Map, x: i32, y: i32) -> u16 {
     }
 }
 
+/// Returns the position on screen of a tile. The map is drawn relative to the position of the
+/// player, who is always at the center of the map area.
+pub fn screen_position(player_pos: Position, pos: Position) -> (i32, i32) {
+    let console_width_for_map = CONSOLE_WIDTH;
+    let console_height_for_map = CONSOLE_HEIGHT - 6;
+    let player_screen_pos = (console_width_for_map / 2, console_height_for_map / 2);
+
+    (
+        pos.x as i32 + player_screen_pos.0 as i32 - player_pos.x as i32,
+        pos.y as i32 + player_screen_pos.1 as i32 - player_pos.y as i32,
+    )
+}
+
 /// Renders the [`Map`] to the screen.
 pub fn render_game_system(
     map: Res<Map>,
@@ -70,11 +85,7 @@ pub fn render_game_system(
     let mut x = 0;
     let mut y = 0;
 
-    let console_width_for_map = CONSOLE_WIDTH;
-    let console_height_for_map = CONSOLE_HEIGHT - 6;
-
     let player_pos = player.single();
-    let player_screen_pos = (console_width_for_map / 2, console_height_for_map / 2);
 
     for (((tile, &revealed), &visible), contents) in map
         .tiles
@@ -110,6 +121,14 @@ pub fn render_game_system(
                         fg = RGB::from_u8(179, 118, 112);
                         glyph = '.' as u16;
                     }
+                    Tile::DownStairs => {
+                        fg = RGB::named(CYAN);
+                        glyph = '>' as u16;
+                    }
+                    Tile::UpStairs => {
+                        fg = RGB::named(CYAN);
+                        glyph = '<' as u16;
+                    }
                 }
                 if visible {
                     // Show bloodstains.
@@ -126,9 +145,13 @@ pub fn render_game_system(
                 }
             }
 
-            // Calculate position of tile on screen (relative to position of player).
-            let x_pos = x + player_screen_pos.0 as i32 - player_pos.x as i32;
-            let y_pos = y + player_screen_pos.1 as i32 - player_pos.y as i32;
+            let (x_pos, y_pos) = screen_position(
+                *player_pos,
+                Position {
+                    x: x as u32,
+                    y: y as u32,
+                },
+            );
             // We don't need to check if the tile is outside the screen because it is already
             // checked by the `.set` method.
             ctx.set(x_pos, y_pos, fg, bg, glyph);
@@ -155,10 +178,39 @@ pub fn render_game_system(
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum MainMenuSelection {
     NewGame,
+    NewPermadeathGame,
+    EnterSeed,
+    DailyChallenge,
     LoadGame,
+    HallOfFame,
     Quit,
 }
 
+impl MainMenuSelection {
+    /// All the entries of the main menu, in order.
+    pub const ALL: [MainMenuSelection; 7] = [
+        MainMenuSelection::NewGame,
+        MainMenuSelection::NewPermadeathGame,
+        MainMenuSelection::EnterSeed,
+        MainMenuSelection::DailyChallenge,
+        MainMenuSelection::LoadGame,
+        MainMenuSelection::HallOfFame,
+        MainMenuSelection::Quit,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MainMenuSelection::NewGame => "Begin New Game",
+            MainMenuSelection::NewPermadeathGame => "Begin New Game (Permadeath)",
+            MainMenuSelection::EnterSeed => "Enter Seed",
+            MainMenuSelection::DailyChallenge => "Daily Challenge",
+            MainMenuSelection::LoadGame => "Load Game",
+            MainMenuSelection::HallOfFame => "Hall of Fame",
+            MainMenuSelection::Quit => "Quit",
+        }
+    }
+}
+
 pub enum MainMenuResult {
     NoSelection { selected: MainMenuSelection },
     Selected { selected: MainMenuSelection },
@@ -183,23 +235,20 @@ pub fn render_main_menu_system(
         }
     };
 
-    if selected == MainMenuSelection::NewGame {
-        ctx.print_color_centered(24, RGB::named(MAGENTA), RGB::named(BLACK), "Begin New Game");
-    } else {
-        ctx.print_color_centered(24, RGB::named(WHITE), RGB::named(BLACK), "Begin New Game");
+    for (i, &entry) in MainMenuSelection::ALL.iter().enumerate() {
+        let fg = if entry == selected {
+            RGB::named(MAGENTA)
+        } else {
+            RGB::named(WHITE)
+        };
+        ctx.print_color_centered(24 + i as u32, fg, RGB::named(BLACK), entry.label());
     }
 
-    if selected == MainMenuSelection::LoadGame {
-        ctx.print_color_centered(25, RGB::named(MAGENTA), RGB::named(BLACK), "Load Game");
-    } else {
-        ctx.print_color_centered(25, RGB::named(WHITE), RGB::named(BLACK), "Load Game");
-    }
-
-    if selected == MainMenuSelection::Quit {
-        ctx.print_color_centered(26, RGB::named(MAGENTA), RGB::named(BLACK), "Quit");
-    } else {
-        ctx.print_color_centered(26, RGB::named(WHITE), RGB::named(BLACK), "Quit");
-    }
+    let index = MainMenuSelection::ALL
+        .iter()
+        .position(|&entry| entry == selected)
+        .unwrap();
+    let count = MainMenuSelection::ALL.len();
 
     *main_menu_result = match ctx.key {
         None => MainMenuResult::NoSelection { selected },
@@ -207,28 +256,299 @@ pub fn render_main_menu_system(
             VirtualKeyCode::Escape => MainMenuResult::NoSelection {
                 selected: MainMenuSelection::Quit,
             },
-            VirtualKeyCode::Up => {
-                let new_selection = match selected {
-                    MainMenuSelection::NewGame => MainMenuSelection::Quit,
-                    MainMenuSelection::LoadGame => MainMenuSelection::NewGame,
-                    MainMenuSelection::Quit => MainMenuSelection::LoadGame,
-                };
-                MainMenuResult::NoSelection {
-                    selected: new_selection,
-                }
-            }
-            VirtualKeyCode::Down => {
-                let new_selection = match selected {
-                    MainMenuSelection::NewGame => MainMenuSelection::LoadGame,
-                    MainMenuSelection::LoadGame => MainMenuSelection::Quit,
-                    MainMenuSelection::Quit => MainMenuSelection::NewGame,
-                };
-                MainMenuResult::NoSelection {
-                    selected: new_selection,
-                }
-            }
+            VirtualKeyCode::Up => MainMenuResult::NoSelection {
+                selected: MainMenuSelection::ALL[(index + count - 1) % count],
+            },
+            VirtualKeyCode::Down => MainMenuResult::NoSelection {
+                selected: MainMenuSelection::ALL[(index + 1) % count],
+            },
             VirtualKeyCode::Return => MainMenuResult::Selected { selected },
             _ => MainMenuResult::NoSelection { selected },
         },
     };
 }
+
+/// Maximum number of digits of a seed (`u64::MAX` has 20 digits).
+const MAX_SEED_DIGITS: usize = 20;
+
+pub enum SeedEntryResult {
+    /// The seed typed so far.
+    NoSelection {
+        text: String,
+    },
+    Selected {
+        seed: u64,
+    },
+    Cancel,
+}
+
+/// Renders the seed entry screen ([`RunState::ShowSeedEntry`]).
+pub fn render_seed_entry_system(
+    mut seed_entry_result: ResMut<SeedEntryResult>,
+    mut ctx: ResMut<BTerm>,
+) {
+    ctx.cls();
+
+    let mut text = match &*seed_entry_result {
+        SeedEntryResult::NoSelection { text } => text.clone(),
+        _ => String::new(),
+    };
+
+    ctx.print_color_centered(20, RGB::named(YELLOW), RGB::named(BLACK), "Enter a seed:");
+    ctx.print_color_centered(
+        22,
+        RGB::named(MAGENTA),
+        RGB::named(BLACK),
+        format!("{}_", text),
+    );
+    ctx.print_color_centered(
+        25,
+        RGB::named(GRAY),
+        RGB::named(BLACK),
+        "ENTER to start, ESCAPE to cancel",
+    );
+
+    let digit = match ctx.key {
+        Some(VirtualKeyCode::Key0) | Some(VirtualKeyCode::Numpad0) => Some('0'),
+        Some(VirtualKeyCode::Key1) | Some(VirtualKeyCode::Numpad1) => Some('1'),
+        Some(VirtualKeyCode::Key2) | Some(VirtualKeyCode::Numpad2) => Some('2'),
+        Some(VirtualKeyCode::Key3) | Some(VirtualKeyCode::Numpad3) => Some('3'),
+        Some(VirtualKeyCode::Key4) | Some(VirtualKeyCode::Numpad4) => Some('4'),
+        Some(VirtualKeyCode::Key5) | Some(VirtualKeyCode::Numpad5) => Some('5'),
+        Some(VirtualKeyCode::Key6) | Some(VirtualKeyCode::Numpad6) => Some('6'),
+        Some(VirtualKeyCode::Key7) | Some(VirtualKeyCode::Numpad7) => Some('7'),
+        Some(VirtualKeyCode::Key8) | Some(VirtualKeyCode::Numpad8) => Some('8'),
+        Some(VirtualKeyCode::Key9) | Some(VirtualKeyCode::Numpad9) => Some('9'),
+        _ => None,
+    };
+
+    *seed_entry_result = match ctx.key {
+        Some(VirtualKeyCode::Escape) => SeedEntryResult::Cancel,
+        // Seeds that do not fit in a `u64` cannot be selected.
+        Some(VirtualKeyCode::Return) | Some(VirtualKeyCode::NumpadEnter) => match text.parse() {
+            Ok(seed) => SeedEntryResult::Selected { seed },
+            Err(_) => SeedEntryResult::NoSelection { text },
+        },
+        Some(VirtualKeyCode::Back) => {
+            text.pop();
+            SeedEntryResult::NoSelection { text }
+        }
+        _ => {
+            if let Some(digit) = digit.filter(|_| text.len() < MAX_SEED_DIGITS) {
+                text.push(digit);
+            }
+            SeedEntryResult::NoSelection { text }
+        }
+    };
+}
+
+pub enum SaveSlotMenuResult {
+    NoSelection { selected: usize },
+    Selected { selected: usize },
+    SaveAndQuit { selected: usize },
+    Delete { selected: usize },
+    Cancel,
+}
+
+impl SaveSlotMenuResult {
+    /// Returns the slot that is under the cursor.
+    pub fn selected(&self) -> usize {
+        match *self {
+            SaveSlotMenuResult::NoSelection { selected }
+            | SaveSlotMenuResult::Selected { selected }
+            | SaveSlotMenuResult::SaveAndQuit { selected }
+            | SaveSlotMenuResult::Delete { selected } => selected,
+            SaveSlotMenuResult::Cancel => 0,
+        }
+    }
+}
+
+/// Renders the save slot picker.
+///
+/// The picker is used for loading a game from the main menu ([`RunState::ShowLoadSlots`]) and for
+/// saving the current game ([`RunState::ShowSaveSlots`]), overwriting the selected slot. When
+/// saving, the game can either continue or quit after the save. A permadeath game can only be
+/// saved into its [`ActiveSlot`], the other slots are grayed out, and only when quitting.
+pub fn render_save_slots_menu_system(
+    state: Res<State<RunState>>,
+    save_slots: Res<SaveSlots>,
+    run: Res<Run>,
+    active_slot: Res<ActiveSlot>,
+    mut save_slot_menu_result: ResMut<SaveSlotMenuResult>,
+    mut ctx: ResMut<BTerm>,
+) {
+    let saving = *state.current() == RunState::ShowSaveSlots;
+    if !saving {
+        // When saving, the picker is drawn on top of the game.
+        ctx.cls();
+    }
+
+    let selected = save_slot_menu_result.selected();
+    let is_used = |slot: usize| matches!(save_slots.slots.get(slot), Some(Some(_)));
+    let can_save = |slot: usize| run.can_save_into(slot, *active_slot);
+
+    let y = 20;
+    ctx.draw_box(
+        5,
+        y - 2,
+        69,
+        SAVE_SLOTS as i32 + 3,
+        RGB::named(WHITE),
+        RGB::named(BLACK),
+    );
+    ctx.print_color(
+        8,
+        y - 2,
+        RGB::named(YELLOW),
+        RGB::named(BLACK),
+        if saving { "Save Game" } else { "Load Game" },
+    );
+    ctx.print_color(
+        8,
+        y + SAVE_SLOTS as i32 + 1,
+        RGB::named(YELLOW),
+        RGB::named(BLACK),
+        if saving && run.permadeath {
+            "Q to save and quit, DELETE to delete, ESCAPE to cancel"
+        } else if saving {
+            "ENTER to save, Q to save and quit, DELETE to delete, ESCAPE to cancel"
+        } else {
+            "ENTER to select, DELETE to delete, ESCAPE to cancel"
+        },
+    );
+
+    for slot in 0..SAVE_SLOTS {
+        let description = match save_slots.slots.get(slot) {
+            Some(Some(metadata)) => format!(
+                "{}. {:<12} Depth {:<3} HP {:>3}/{:<3} Turn {:<6} {}",
+                slot + 1,
+                metadata.character_name,
+                metadata.depth,
+                metadata.hp,
+                metadata.max_hp,
+                metadata.turn,
+                format_timestamp(metadata.timestamp)
+            ),
+            _ => format!("{}. <empty>", slot + 1),
+        };
+        let fg = if slot == selected {
+            RGB::named(MAGENTA)
+        } else if saving && !can_save(slot) {
+            RGB::named(GRAY)
+        } else {
+            RGB::named(WHITE)
+        };
+        ctx.print_color(7, y + slot as i32, fg, RGB::named(BLACK), &description);
+    }
+
+    *save_slot_menu_result = match ctx.key {
+        None => SaveSlotMenuResult::NoSelection { selected },
+        Some(key) => match key {
+            VirtualKeyCode::Escape => SaveSlotMenuResult::Cancel,
+            VirtualKeyCode::Up => SaveSlotMenuResult::NoSelection {
+                selected: (selected + SAVE_SLOTS - 1) % SAVE_SLOTS,
+            },
+            VirtualKeyCode::Down => SaveSlotMenuResult::NoSelection {
+                selected: (selected + 1) % SAVE_SLOTS,
+            },
+            // An empty slot cannot be loaded and a permadeath game cannot continue after saving.
+            VirtualKeyCode::Return
+                if (saving && !run.permadeath && can_save(selected))
+                    || (!saving && is_used(selected)) =>
+            {
+                SaveSlotMenuResult::Selected { selected }
+            }
+            VirtualKeyCode::Q if saving && can_save(selected) => {
+                SaveSlotMenuResult::SaveAndQuit { selected }
+            }
+            VirtualKeyCode::Delete if is_used(selected) => SaveSlotMenuResult::Delete { selected },
+            _ => SaveSlotMenuResult::NoSelection { selected },
+        },
+    };
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum HighScoresResult {
+    NoResponse,
+    Close,
+}
+
+/// Renders the hall of fame ([`RunState::ShowHighScores`]).
+pub fn render_high_scores_system(
+    high_scores: Res<HighScores>,
+    mut high_scores_result: ResMut<HighScoresResult>,
+    mut ctx: ResMut<BTerm>,
+) {
+    ctx.cls();
+
+    let y = 15;
+    ctx.draw_box(
+        2,
+        y - 2,
+        75,
+        MAX_HIGH_SCORES as i32 + 4,
+        RGB::named(WHITE),
+        RGB::named(BLACK),
+    );
+    ctx.print_color(
+        5,
+        y - 2,
+        RGB::named(YELLOW),
+        RGB::named(BLACK),
+        "Hall of Fame",
+    );
+    ctx.print_color(
+        5,
+        y + MAX_HIGH_SCORES as i32 + 2,
+        RGB::named(YELLOW),
+        RGB::named(BLACK),
+        "ESCAPE to go back",
+    );
+
+    ctx.print_color(
+        4,
+        y,
+        RGB::named(GRAY),
+        RGB::named(BLACK),
+        format!(
+            "{:>2}  {:>6}  {:<12} {:>5} {:>5} {:>6}  {:<16} {}",
+            "#", "Score", "Name", "Depth", "Kills", "Turns", "Cause", "Date"
+        ),
+    );
+    if high_scores.entries.is_empty() {
+        ctx.print_color(
+            4,
+            y + 1,
+            RGB::named(WHITE),
+            RGB::named(BLACK),
+            "No games have been played yet.",
+        );
+    }
+    for (rank, entry) in high_scores.entries.iter().enumerate() {
+        let date = format_timestamp(entry.timestamp);
+        let line = format!(
+            "{:>2}. {:>6}  {:<12} {:>5} {:>5} {:>6}  {:<16} {}",
+            rank + 1,
+            entry.score,
+            entry.character_name,
+            entry.depth,
+            entry.kills,
+            entry.turns,
+            entry.cause,
+            // Only the day fits on the screen.
+            &date[..10]
+        );
+        ctx.print_color(
+            4,
+            y + 1 + rank as i32,
+            RGB::named(WHITE),
+            RGB::named(BLACK),
+            &line,
+        );
+    }
+
+    *high_scores_result = match ctx.key {
+        Some(VirtualKeyCode::Escape) | Some(VirtualKeyCode::Return) => HighScoresResult::Close,
+        _ => HighScoresResult::NoResponse,
+    };
+}
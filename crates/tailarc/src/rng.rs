@@ -0,0 +1,90 @@
+//! Deterministic random number generation.
+//!
+//! All the randomness of a game comes from its seed so that a seed fully reproduces a game. Every
+//! level is generated from its own RNG derived from the seed and the depth, so that levels do not
+//! depend on what happened during the game.
+
+use rand::rngs::StdRng;
+use rand::{thread_rng, Error, Rng, RngCore, SeedableRng};
+
+use crate::save::civil_date;
+
+/// Returns the seed of the daily challenge for the day of the given timestamp (in seconds since the
+/// Unix epoch). The seed is the UTC date written as `YYYYMMDD` so that it is easy to share.
+pub fn daily_seed(timestamp: u64) -> u64 {
+    let (year, month, day) = civil_date(timestamp);
+    (year * 10000 + month * 100 + day) as u64
+}
+
+/// A resource containing the seed of the game and the RNG used for gameplay.
+///
+/// Note that the output of [`StdRng`] may change between versions of `rand`, so a seed only
+/// reproduces a game with the same version of the game.
+#[derive(Debug, Clone)]
+pub struct GameRng {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl GameRng {
+    /// Creates a new RNG from the given seed.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Creates the RNG of a game with the given seed that is resumed at the given turn.
+    ///
+    /// The state of the gameplay RNG is not saved, so the gameplay RNG is reseeded from the turn
+    /// and some entropy. Otherwise every load would restart the rolls from the beginning of the
+    /// game, which makes them predictable. Levels are still generated from the seed only.
+    pub fn resume(seed: u64, turn: u32) -> Self {
+        let gameplay_seed =
+            seed ^ (turn as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ thread_rng().gen::<u64>();
+        Self {
+            seed,
+            rng: StdRng::seed_from_u64(gameplay_seed),
+        }
+    }
+
+    /// Creates a new RNG with a random seed.
+    pub fn from_entropy() -> Self {
+        Self::new(thread_rng().gen())
+    }
+
+    /// Returns the seed of the game.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Returns the RNG used to generate the level at the given depth.
+    pub fn level_rng(&self, depth: i32) -> StdRng {
+        level_rng(self.seed, depth)
+    }
+}
+
+/// Returns the RNG used to generate the level at the given depth for a game with the given seed.
+pub fn level_rng(seed: u64, depth: i32) -> StdRng {
+    // Mix the depth into the seed so that consecutive depths get unrelated levels.
+    StdRng::seed_from_u64(seed ^ (depth as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
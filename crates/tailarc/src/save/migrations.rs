@@ -0,0 +1,520 @@
+//! Upgrades save files written by older versions of the game.
+//!
+//! Migrations operate on the raw JSON of the save file before it is deserialized. Every time the
+//! format of the save file changes (e.g. a field is added to a component or a component is
+//! renamed), [`SAVE_VERSION`] should be incremented and a migration from the previous version
+//! should be appended to [`MIGRATIONS`].
+
+use serde_json::{json, Value};
+
+use super::{LoadError, SAVE_VERSION};
+
+/// A function that upgrades a save file by exactly one version.
+type Migration = fn(&mut Value) -> Result<(), LoadError>;
+
+/// `MIGRATIONS[n]` upgrades a save file from version `n` to version `n + 1`.
+pub(super) const MIGRATIONS: &[Migration] = &[
+    v0_to_v1, v1_to_v2, v2_to_v3, v3_to_v4, v4_to_v5, v5_to_v6, v6_to_v7, v7_to_v8, v8_to_v9,
+    v9_to_v10, v10_to_v11,
+];
+
+/// Returns the version of the save file.
+///
+/// Save files written before versioning was introduced are a bare scene (a JSON array) and are
+/// considered to be version `0`.
+pub fn save_version(save: &Value) -> Result<u32, LoadError> {
+    match save {
+        Value::Array(_) => Ok(0),
+        Value::Object(object) => object
+            .get("version")
+            .and_then(Value::as_u64)
+            .map(|version| version as u32)
+            .ok_or(LoadError::InvalidFormat("missing save version")),
+        _ => Err(LoadError::InvalidFormat("expected an object or an array")),
+    }
+}
+
+/// Returns the component with the given type name of an entity in the scene.
+fn find_component<'a>(entity: &'a Value, type_name: &str) -> Option<&'a Value> {
+    entity["components"]
+        .as_array()?
+        .iter()
+        .find(|component| component["type"] == type_name)
+}
+
+/// Upgrades the save file to [`SAVE_VERSION`] by running all the migrations from its version.
+pub fn migrate(save: &mut Value) -> Result<(), LoadError> {
+    let version = save_version(save)?;
+    if version > SAVE_VERSION {
+        return Err(LoadError::UnsupportedVersion(version));
+    }
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        tracing::info!("migrating save file from version {} to {}", from, from + 1);
+        migration(save)?;
+        save["version"] = json!(from + 1);
+    }
+
+    Ok(())
+}
+
+/// Version `1` wraps the scene into a container with the map and the game log.
+///
+/// The map was not saved in version `0` so it is left empty.
+fn v0_to_v1(save: &mut Value) -> Result<(), LoadError> {
+    let scene = save.take();
+    *save = json!({
+        "version": 1,
+        "map": null,
+        "game_log": [],
+        "scene": scene,
+    });
+    Ok(())
+}
+
+/// Version `2` adds the metadata of the save file. It is computed from the player entity and the
+/// map. The turn and the time of the save are unknown.
+fn v1_to_v2(save: &mut Value) -> Result<(), LoadError> {
+    let player = save["scene"]
+        .as_array()
+        .ok_or(LoadError::InvalidFormat("scene is not an array"))?
+        .iter()
+        .find(|entity| find_component(entity, "tailarc::components::Player").is_some());
+
+    let character_name = player
+        .and_then(|player| find_component(player, "tailarc::components::EntityName"))
+        .and_then(|name| name.pointer("/struct/name/value"))
+        .and_then(Value::as_str)
+        .unwrap_or("Player")
+        .to_string();
+    let stat = |field: &str| {
+        player
+            .and_then(|player| find_component(player, "tailarc::components::CombatStats"))
+            .and_then(|stats| stats.pointer(&format!("/struct/{}/value", field)))
+            .and_then(Value::as_i64)
+            .unwrap_or(0)
+    };
+    let metadata = json!({
+        "character_name": character_name,
+        "depth": save.pointer("/map/depth").and_then(Value::as_i64).unwrap_or(1),
+        "hp": stat("hp"),
+        "max_hp": stat("max_hp"),
+        "turn": 0,
+        "timestamp": 0,
+    });
+
+    save["metadata"] = metadata;
+    Ok(())
+}
+
+/// Version `3` adds permadeath mode and run ids. Older games were not played in permadeath mode so
+/// their run id does not matter.
+fn v2_to_v3(save: &mut Value) -> Result<(), LoadError> {
+    let metadata = save["metadata"]
+        .as_object_mut()
+        .ok_or(LoadError::InvalidFormat("metadata is not an object"))?;
+    metadata.insert("run_id".to_string(), json!(0));
+    metadata.insert("permadeath".to_string(), json!(false));
+    Ok(())
+}
+
+/// Version `4` adds the number of monsters killed. Kills were not counted before.
+fn v3_to_v4(save: &mut Value) -> Result<(), LoadError> {
+    save["kills"] = json!({});
+    Ok(())
+}
+
+/// Version `5` adds the seed of the game. The seed of older games is unknown.
+fn v4_to_v5(save: &mut Value) -> Result<(), LoadError> {
+    save["seed"] = Value::Null;
+    Ok(())
+}
+
+/// Version `6` adds the levels that the player is not on. Older games only had one level.
+fn v5_to_v6(save: &mut Value) -> Result<(), LoadError> {
+    save["levels"] = json!({});
+    Ok(())
+}
+
+/// Version `7` adds the movement mode of mobs. All the mobs used to chase the player.
+fn v6_to_v7(save: &mut Value) -> Result<(), LoadError> {
+    let scene = save["scene"]
+        .as_array_mut()
+        .ok_or(LoadError::InvalidFormat("scene is not an array"))?;
+    for entity in scene {
+        if find_component(entity, "tailarc::components::Mob").is_none() {
+            continue;
+        }
+        entity["components"]
+            .as_array_mut()
+            .ok_or(LoadError::InvalidFormat("components is not an array"))?
+            .push(json!({
+                "type": "tailarc::components::Movement",
+                "value": {
+                    "mode": "chase",
+                    "last_known_player_position": null,
+                },
+            }));
+    }
+    Ok(())
+}
+
+/// Version `8` adds the attributes of the player and mobs. Characters used to have average
+/// attributes, which give no bonus.
+fn v7_to_v8(save: &mut Value) -> Result<(), LoadError> {
+    let scene = save["scene"]
+        .as_array_mut()
+        .ok_or(LoadError::InvalidFormat("scene is not an array"))?;
+    for entity in scene {
+        if find_component(entity, "tailarc::components::Player").is_none()
+            && find_component(entity, "tailarc::components::Mob").is_none()
+        {
+            continue;
+        }
+        // The average attribute in version `8`, which must not follow later changes.
+        let attribute = json!({ "type": "i32", "value": 10 });
+        entity["components"]
+            .as_array_mut()
+            .ok_or(LoadError::InvalidFormat("components is not an array"))?
+            .push(json!({
+                "type": "tailarc::components::Attributes",
+                "struct": {
+                    "might": attribute,
+                    "fitness": attribute,
+                    "quickness": attribute,
+                    "intelligence": attribute,
+                },
+            }));
+    }
+    Ok(())
+}
+
+/// Version `9` adds the damage, area of effect, confusion and teleport effects of consumable items,
+/// and the confusion of entities. Older items only healed and no entity was confused.
+fn v8_to_v9(save: &mut Value) -> Result<(), LoadError> {
+    let scene = save["scene"]
+        .as_array_mut()
+        .ok_or(LoadError::InvalidFormat("scene is not an array"))?;
+    for entity in scene {
+        let effects = entity["components"]
+            .as_array_mut()
+            .ok_or(LoadError::InvalidFormat("components is not an array"))?
+            .iter_mut()
+            .find(|component| component["type"] == "tailarc::components::ConsumableEffects");
+        let effects = match effects {
+            Some(effects) => effects["struct"]
+                .as_object_mut()
+                .ok_or(LoadError::InvalidFormat(
+                    "consumable effects are not a struct",
+                ))?,
+            None => continue,
+        };
+        let none = json!({ "type": "core::option::Option<i32>", "value": null });
+        effects.insert("damage".to_string(), none.clone());
+        effects.insert("area_of_effect".to_string(), none.clone());
+        effects.insert("confusion".to_string(), none);
+        effects.insert(
+            "teleport".to_string(),
+            json!({ "type": "bool", "value": false }),
+        );
+    }
+    Ok(())
+}
+
+/// Version `10` replaces the confusion of entities and of consumable items with status effects.
+fn v9_to_v10(save: &mut Value) -> Result<(), LoadError> {
+    let scene = save["scene"]
+        .as_array_mut()
+        .ok_or(LoadError::InvalidFormat("scene is not an array"))?;
+    for entity in scene {
+        let components = entity["components"]
+            .as_array_mut()
+            .ok_or(LoadError::InvalidFormat("components is not an array"))?;
+        for component in components {
+            if component["type"] == "tailarc::components::Confusion" {
+                let turns = component["struct"]["turns"]["value"].clone();
+                *component = json!({
+                    "type": "tailarc::components::StatusEffects",
+                    "value": {
+                        "effects": [{ "kind": "confusion", "turns": turns }],
+                    },
+                });
+            } else if component["type"] == "tailarc::components::ConsumableEffects" {
+                let fields = match component["struct"].as_object_mut() {
+                    Some(fields) => fields,
+                    None => continue,
+                };
+                if let Some(confusion) = fields.remove("confusion") {
+                    let status_effect = match &confusion["value"] {
+                        Value::Null => Value::Null,
+                        turns => json!({ "kind": "confusion", "turns": turns }),
+                    };
+                    fields.insert(
+                        "status_effect".to_string(),
+                        json!({
+                            "type": "core::option::Option<tailarc::components::StatusEffect>",
+                            "value": status_effect,
+                        }),
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Version `11` adds the energy of the player and mobs. Everyone used to act once per turn, at the
+/// normal speed.
+fn v10_to_v11(save: &mut Value) -> Result<(), LoadError> {
+    let scene = save["scene"]
+        .as_array_mut()
+        .ok_or(LoadError::InvalidFormat("scene is not an array"))?;
+    for entity in scene {
+        if find_component(entity, "tailarc::components::Player").is_none()
+            && find_component(entity, "tailarc::components::Mob").is_none()
+        {
+            continue;
+        }
+        entity["components"]
+            .as_array_mut()
+            .ok_or(LoadError::InvalidFormat("components is not an array"))?
+            .push(json!({
+                "type": "tailarc::components::Energy",
+                "struct": {
+                    "energy": { "type": "i32", "value": 100 },
+                    "speed": { "type": "i32", "value": 100 },
+                },
+            }));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn v0_to_v1_wraps_scene() {
+        let mut save = json!([{"entity": 0, "components": []}]);
+        v0_to_v1(&mut save).unwrap();
+        assert_eq!(
+            save,
+            json!({
+                "version": 1,
+                "map": null,
+                "game_log": [],
+                "scene": [{"entity": 0, "components": []}],
+            })
+        );
+    }
+
+    #[test]
+    fn v1_to_v2_computes_metadata() {
+        let mut save = json!({
+            "version": 1,
+            "map": {"depth": 3},
+            "game_log": [],
+            "scene": [{
+                "entity": 0,
+                "components": [
+                    {"type": "tailarc::components::Player", "struct": {}},
+                    {
+                        "type": "tailarc::components::EntityName",
+                        "struct": {"name": {"type": "alloc::string::String", "value": "Ada"}},
+                    },
+                    {
+                        "type": "tailarc::components::CombatStats",
+                        "struct": {
+                            "max_hp": {"type": "i32", "value": 100},
+                            "hp": {"type": "i32", "value": 92},
+                        },
+                    },
+                ],
+            }],
+        });
+        v1_to_v2(&mut save).unwrap();
+        assert_eq!(
+            save["metadata"],
+            json!({
+                "character_name": "Ada",
+                "depth": 3,
+                "hp": 92,
+                "max_hp": 100,
+                "turn": 0,
+                "timestamp": 0,
+            })
+        );
+    }
+
+    #[test]
+    fn v2_to_v3_disables_permadeath() {
+        let mut save = json!({"version": 2, "metadata": {"turn": 42}});
+        v2_to_v3(&mut save).unwrap();
+        assert_eq!(
+            save["metadata"],
+            json!({"turn": 42, "run_id": 0, "permadeath": false})
+        );
+    }
+
+    #[test]
+    fn v3_to_v4_adds_no_kills() {
+        let mut save = json!({"version": 3});
+        v3_to_v4(&mut save).unwrap();
+        assert_eq!(save["kills"], json!({}));
+    }
+
+    #[test]
+    fn v4_to_v5_has_unknown_seed() {
+        let mut save = json!({"version": 4});
+        v4_to_v5(&mut save).unwrap();
+        assert_eq!(save["seed"], Value::Null);
+    }
+
+    #[test]
+    fn v5_to_v6_has_no_other_levels() {
+        let mut save = json!({"version": 5});
+        v5_to_v6(&mut save).unwrap();
+        assert_eq!(save["levels"], json!({}));
+    }
+
+    #[test]
+    fn v6_to_v7_makes_mobs_chase() {
+        let mut save = json!({
+            "version": 6,
+            "scene": [
+                {"entity": 0, "components": [{"type": "tailarc::components::Player", "struct": {}}]},
+                {"entity": 1, "components": [{"type": "tailarc::components::Mob", "struct": {}}]},
+            ],
+        });
+        v6_to_v7(&mut save).unwrap();
+        assert_eq!(save["scene"][0]["components"].as_array().unwrap().len(), 1);
+        assert_eq!(
+            save["scene"][1]["components"][1],
+            json!({
+                "type": "tailarc::components::Movement",
+                "value": {"mode": "chase", "last_known_player_position": null},
+            })
+        );
+    }
+
+    #[test]
+    fn v7_to_v8_gives_average_attributes() {
+        let mut save = json!({
+            "version": 7,
+            "scene": [
+                {"entity": 0, "components": [{"type": "tailarc::components::Player", "struct": {}}]},
+                {"entity": 1, "components": [{"type": "tailarc::components::Mob", "struct": {}}]},
+                {"entity": 2, "components": [{"type": "tailarc::components::Item", "struct": {}}]},
+            ],
+        });
+        v7_to_v8(&mut save).unwrap();
+        let attribute = json!({"type": "i32", "value": 10});
+        let attributes = json!({
+            "type": "tailarc::components::Attributes",
+            "struct": {
+                "might": attribute,
+                "fitness": attribute,
+                "quickness": attribute,
+                "intelligence": attribute,
+            },
+        });
+        assert_eq!(save["scene"][0]["components"][1], attributes);
+        assert_eq!(save["scene"][1]["components"][1], attributes);
+        assert_eq!(save["scene"][2]["components"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn v8_to_v9_adds_no_effects() {
+        let mut save = json!({
+            "version": 8,
+            "scene": [{
+                "entity": 0,
+                "components": [{
+                    "type": "tailarc::components::ConsumableEffects",
+                    "struct": {"heal": {"type": "core::option::Option<i32>", "value": 10}},
+                }],
+            }],
+        });
+        v8_to_v9(&mut save).unwrap();
+        let none = json!({"type": "core::option::Option<i32>", "value": null});
+        assert_eq!(
+            save["scene"][0]["components"][0]["struct"],
+            json!({
+                "heal": {"type": "core::option::Option<i32>", "value": 10},
+                "damage": none,
+                "area_of_effect": none,
+                "confusion": none,
+                "teleport": {"type": "bool", "value": false},
+            })
+        );
+    }
+
+    #[test]
+    fn v9_to_v10_replaces_confusion_with_status_effects() {
+        let mut save = json!({
+            "version": 9,
+            "scene": [
+                {
+                    "entity": 0,
+                    "components": [{
+                        "type": "tailarc::components::Confusion",
+                        "struct": {"turns": {"type": "i32", "value": 2}},
+                    }],
+                },
+                {
+                    "entity": 1,
+                    "components": [{
+                        "type": "tailarc::components::ConsumableEffects",
+                        "struct": {"confusion": {"type": "core::option::Option<i32>", "value": 4}},
+                    }],
+                },
+            ],
+        });
+        v9_to_v10(&mut save).unwrap();
+        assert_eq!(
+            save["scene"][0]["components"][0],
+            json!({
+                "type": "tailarc::components::StatusEffects",
+                "value": {"effects": [{"kind": "confusion", "turns": 2}]},
+            })
+        );
+        assert_eq!(
+            save["scene"][1]["components"][0]["struct"],
+            json!({
+                "status_effect": {
+                    "type": "core::option::Option<tailarc::components::StatusEffect>",
+                    "value": {"kind": "confusion", "turns": 4},
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn v10_to_v11_gives_normal_energy() {
+        let mut save = json!({
+            "version": 10,
+            "scene": [
+                {
+                    "entity": 0,
+                    "components": [{"type": "tailarc::components::Player", "struct": {}}],
+                },
+                {
+                    "entity": 1,
+                    "components": [{"type": "tailarc::components::Item", "struct": {}}],
+                },
+            ],
+        });
+        v10_to_v11(&mut save).unwrap();
+        assert_eq!(
+            save["scene"][0]["components"][1],
+            json!({
+                "type": "tailarc::components::Energy",
+                "struct": {
+                    "energy": {"type": "i32", "value": 100},
+                    "speed": {"type": "i32", "value": 100},
+                },
+            })
+        );
+        assert_eq!(save["scene"][1]["components"].as_array().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,624 @@
+//! Save file format.
+
+mod migrations;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy_reflect::TypeRegistryArc;
+use bevy_scene::serde::{SceneDeserializer, SceneSerializer};
+use bevy_scene::{DynamicScene, SceneSpawnError};
+use directories::ProjectDirs;
+use serde::de::DeserializeSeed;
+use serde::{Deserialize, Serialize};
+
+use crate::map::Map;
+
+pub use self::migrations::{migrate, save_version};
+
+/// Version of the save file format. Should be incremented every time the format changes. See
+/// [`migrations`] for upgrading older save files.
+pub const SAVE_VERSION: u32 = 11;
+
+/// Number of save slots.
+pub const SAVE_SLOTS: usize = 5;
+
+/// Returns the directory in which save data is stored.
+pub fn data_dir() -> PathBuf {
+    let project_dirs = ProjectDirs::from("", "", "Tailarc").expect("could not create ProjectDirs");
+    project_dirs.data_dir().to_path_buf()
+}
+
+/// Returns the path of the save file for the given slot.
+pub fn slot_path(slot: usize) -> PathBuf {
+    [
+        data_dir().as_path(),
+        Path::new(&format!("slot{}.json", slot)),
+    ]
+    .into_iter()
+    .collect()
+}
+
+/// Moves the save file written before save slots were introduced into the first slot (if it is
+/// free).
+fn migrate_legacy_save() {
+    let legacy_path: PathBuf = [data_dir().as_path(), Path::new("save.json")]
+        .into_iter()
+        .collect();
+    if legacy_path.exists() && !slot_path(0).exists() {
+        if let Err(err) = fs::rename(&legacy_path, slot_path(0)) {
+            tracing::warn!("could not move legacy save file into slot 0: {}", err);
+        }
+    }
+}
+
+/// Writes the save file for the given slot.
+///
+/// The data is first written to a temporary file which is then renamed so that the previous save
+/// file is not corrupted if the game crashes while writing.
+pub fn write_slot(slot: usize, data: &str) -> io::Result<()> {
+    fs::create_dir_all(data_dir())?;
+    let path = slot_path(slot);
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, data)?;
+    fs::rename(&tmp_path, &path)
+}
+
+/// Deletes the save file in the given slot.
+pub fn delete_slot(slot: usize) {
+    if let Err(err) = fs::remove_file(slot_path(slot)) {
+        tracing::warn!("could not delete save slot {}: {}", slot, err);
+    }
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+pub fn timestamp_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Returns the date (year, month, day) of a timestamp (in seconds since the Unix epoch) in UTC.
+pub fn civil_date(timestamp: u64) -> (i64, i64, i64) {
+    let days = (timestamp / 86400) as i64;
+
+    // Convert days since the epoch into a civil date.
+    // See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+/// Formats a timestamp (in seconds since the Unix epoch) as `YYYY-MM-DD HH:MM` (UTC).
+pub fn format_timestamp(timestamp: u64) -> String {
+    let (year, month, day) = civil_date(timestamp);
+    let seconds = timestamp % 86400;
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60
+    )
+}
+
+/// Summary of a save file. Displayed in the save slot picker.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveMetadata {
+    pub character_name: String,
+    pub depth: i32,
+    pub hp: i32,
+    pub max_hp: i32,
+    pub turn: u32,
+    /// Time at which the game was saved in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The [`Run::id`] of the game.
+    pub run_id: u64,
+    /// Whether the game is played in permadeath mode. See [`Run`].
+    pub permadeath: bool,
+}
+
+/// A resource containing the metadata of all the save slots.
+///
+/// This is only refreshed by calling [`SaveSlots::read`] because reading all the save files is
+/// expensive.
+#[derive(Debug, Default)]
+pub struct SaveSlots {
+    /// The metadata of each slot or `None` if the slot is empty or could not be read.
+    pub slots: Vec<Option<SaveMetadata>>,
+}
+
+impl SaveSlots {
+    /// Reads the metadata of all the save slots from disk.
+    pub fn read() -> Self {
+        migrate_legacy_save();
+
+        let slots = (0..SAVE_SLOTS)
+            .map(|slot| {
+                let path = slot_path(slot);
+                if !path.exists() {
+                    return None;
+                }
+                match fs::read_to_string(path)
+                    .map_err(LoadError::from)
+                    .and_then(|data| SaveFile::from_json(&data))
+                {
+                    Ok(save) => Some(save.metadata),
+                    Err(err) => {
+                        tracing::warn!("could not read save slot {}: {}", slot, err);
+                        None
+                    }
+                }
+            })
+            .collect();
+        Self { slots }
+    }
+
+    /// Returns the slots that contain a save file of the given run.
+    pub fn slots_of_run(&self, run_id: u64) -> Vec<usize> {
+        (0..SAVE_SLOTS)
+            .filter(|&slot| matches!(self.slots.get(slot), Some(Some(metadata)) if metadata.run_id == run_id))
+            .collect()
+    }
+
+    /// Returns the first slot that does not contain a save file.
+    pub fn first_empty(&self) -> Option<usize> {
+        (0..SAVE_SLOTS).find(|&slot| !matches!(self.slots.get(slot), Some(Some(_))))
+    }
+}
+
+/// A resource containing the save slot that the current game is saved to.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ActiveSlot(pub Option<usize>);
+
+/// A resource describing the game that is being played. It is kept in the [`SaveMetadata`] of its
+/// save files.
+///
+/// In permadeath mode, loading a game consumes its save file (it is written again when the game is
+/// saved), the game can only be saved into its [`ActiveSlot`] and all the save files of the run are
+/// deleted when the player dies, so that death cannot be undone by reloading.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Run {
+    /// Identifies the save files written by the same game.
+    pub id: u64,
+    /// Whether the game is played in permadeath mode. Chosen when starting a new game.
+    pub permadeath: bool,
+}
+
+impl Run {
+    /// Starts a new run with a random id.
+    pub fn new(permadeath: bool) -> Self {
+        Self {
+            id: rand::random(),
+            permadeath,
+        }
+    }
+
+    /// Returns `true` if the game can be saved into `slot`.
+    pub fn can_save_into(&self, slot: usize, active_slot: ActiveSlot) -> bool {
+        !self.permadeath || active_slot.0.is_none() || active_slot.0 == Some(slot)
+    }
+}
+
+/// Default number of player turns between autosaves.
+pub const DEFAULT_AUTOSAVE_INTERVAL: u32 = 50;
+
+/// A resource that keeps track of when the game should be autosaved.
+///
+/// The game is autosaved into the [`ActiveSlot`] every [`Autosave::interval`] turns and every time
+/// the player moves to another depth.
+#[derive(Debug, Clone, Copy)]
+pub struct Autosave {
+    /// Number of player turns between autosaves. `0` disables autosaving every N turns.
+    ///
+    /// Can be configured at runtime with the `TAILARC_AUTOSAVE_INTERVAL` environment variable.
+    pub interval: u32,
+    /// Whether the game is autosaved. Autosaving is disabled in permadeath games, which are only
+    /// saved when quitting, so that a consumed save file is not written again behind the back of
+    /// the player.
+    pub enabled: bool,
+    /// Turn and depth of the last save (or of the start of the game if it has not been saved
+    /// yet). `None` if not known yet.
+    pub last_save: Option<(u32, i32)>,
+}
+
+impl Autosave {
+    pub fn from_env() -> Self {
+        let interval = std::env::var("TAILARC_AUTOSAVE_INTERVAL")
+            .ok()
+            .and_then(|interval| interval.parse().ok())
+            .unwrap_or(DEFAULT_AUTOSAVE_INTERVAL);
+        Self {
+            interval,
+            enabled: true,
+            last_save: None,
+        }
+    }
+
+    /// Returns `true` if the game should be autosaved at the given turn and depth.
+    pub fn is_due(&self, turn: u32, depth: i32) -> bool {
+        match self.last_save {
+            Some((last_turn, last_depth)) => {
+                depth != last_depth || (self.interval != 0 && turn >= last_turn + self.interval)
+            }
+            // Start counting from the first turn instead of saving immediately.
+            None => false,
+        }
+    }
+}
+
+/// The data that is written to the save file.
+///
+/// This is the borrowed counterpart of [`SaveFile`].
+#[derive(Serialize)]
+pub struct SaveData<'a> {
+    pub version: u32,
+    pub metadata: SaveMetadata,
+    /// Seed of the game.
+    pub seed: u64,
+    pub map: &'a Map,
+    /// The levels that the player is not on.
+    pub levels: &'a BTreeMap<i32, Map>,
+    pub game_log: Vec<String>,
+    /// Number of monsters killed by name.
+    pub kills: &'a BTreeMap<String, u32>,
+    /// All the entities in the world.
+    pub scene: SceneSerializer<'a>,
+}
+
+/// The data that is read from the save file.
+#[derive(Deserialize)]
+pub struct SaveFile {
+    pub version: u32,
+    pub metadata: SaveMetadata,
+    /// Seed of the game. Save files from before version `5` do not contain the seed.
+    pub seed: Option<u64>,
+    /// Save files from before version `1` do not contain the map.
+    pub map: Option<Map>,
+    /// The levels that the player is not on.
+    pub levels: BTreeMap<i32, Map>,
+    pub game_log: Vec<String>,
+    /// Number of monsters killed by name.
+    pub kills: BTreeMap<String, u32>,
+    /// All the entities in the world. Use [`SaveFile::deserialize_scene`] to get a
+    /// [`DynamicScene`].
+    pub scene: serde_json::Value,
+}
+
+impl SaveFile {
+    /// Parses a save file, upgrading it to [`SAVE_VERSION`] if it was written by an older version
+    /// of the game.
+    pub fn from_json(data: &str) -> Result<Self, LoadError> {
+        let mut save: serde_json::Value = serde_json::from_str(data)?;
+        migrate(&mut save)?;
+        Ok(serde_json::from_value(save)?)
+    }
+
+    /// Deserializes the entities using the types registered in `type_registry`.
+    pub fn deserialize_scene(
+        &self,
+        type_registry: &TypeRegistryArc,
+    ) -> Result<DynamicScene, serde_json::Error> {
+        SceneDeserializer {
+            type_registry: &type_registry.read(),
+        }
+        .deserialize(&self.scene)
+    }
+}
+
+/// An error that occurred while reading a save file.
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// The entities of the save file could not be spawned.
+    Spawn(SceneSpawnError),
+    /// The save file was written by a newer version of the game.
+    UnsupportedVersion(u32),
+    /// The save file does not have the expected structure.
+    InvalidFormat(&'static str),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "could not read save file: {}", err),
+            LoadError::Json(err) => write!(f, "could not parse save file: {}", err),
+            LoadError::Spawn(err) => write!(f, "could not spawn saved entities: {}", err),
+            LoadError::UnsupportedVersion(version) => write!(
+                f,
+                "save file version {} is newer than the supported version {}",
+                version, SAVE_VERSION
+            ),
+            LoadError::InvalidFormat(reason) => write!(f, "invalid save file: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<std::io::Error> for LoadError {
+    fn from(err: std::io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+impl From<SceneSpawnError> for LoadError {
+    fn from(err: SceneSpawnError) -> Self {
+        LoadError::Spawn(err)
+    }
+}
+
+impl From<serde_json::Error> for LoadError {
+    fn from(err: serde_json::Error) -> Self {
+        LoadError::Json(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_app::App;
+    use bevy_core::CorePlugin;
+    use bevy_ecs::prelude::*;
+
+    use super::*;
+    use crate::components::{
+        register_component_types, Energy, EntityName, EquipmentSlot, Equipped, Item, Mob, Movement,
+        MovementMode, Owned, Player, NORMAL_SPEED,
+    };
+    use crate::map::Tile;
+
+    fn type_registry() -> TypeRegistryArc {
+        let mut app = App::new();
+        app.add_plugin(CorePlugin);
+        let type_registry = app.world.get_resource::<TypeRegistryArc>().unwrap().clone();
+        register_component_types(&mut type_registry.write());
+        type_registry
+    }
+
+    /// Spawns the entities of the save file into a new [`World`].
+    fn load_world(save: &SaveFile) -> World {
+        let type_registry = type_registry();
+        let scene = save.deserialize_scene(&type_registry).unwrap();
+
+        let mut world = World::new();
+        world.insert_resource(type_registry);
+        scene
+            .write_to_world(&mut world, &mut Default::default())
+            .unwrap();
+        world
+    }
+
+    /// Checks the entities that are in all the fixtures.
+    fn check_fixture_entities(world: &mut World) {
+        let player = world
+            .query_filtered::<Entity, With<Player>>()
+            .iter(world)
+            .next()
+            .unwrap();
+
+        let owned: Vec<_> = world
+            .query::<(&EntityName, &Owned)>()
+            .iter(world)
+            .map(|(name, owned)| (name.name.clone(), owned.owner))
+            .collect();
+        assert_eq!(owned, vec![("Health Potion".to_string(), player)]);
+
+        let equipped: Vec<_> = world
+            .query::<(&EntityName, &Equipped)>()
+            .iter(world)
+            .map(|(name, equipped)| (name.name.clone(), equipped.by))
+            .collect();
+        assert_eq!(equipped, vec![("Dagger".to_string(), player)]);
+
+        let movements: Vec<_> = world
+            .query_filtered::<&Movement, With<Mob>>()
+            .iter(world)
+            .map(|movement| movement.mode)
+            .collect();
+        assert_eq!(movements, vec![MovementMode::Chase]);
+
+        let speeds: Vec<_> = world
+            .query::<&Energy>()
+            .iter(world)
+            .map(|energy| energy.speed)
+            .collect();
+        assert_eq!(speeds, vec![NORMAL_SPEED, NORMAL_SPEED]);
+    }
+
+    /// A save file written by every version of the game, indexed by version.
+    const FIXTURES: &[&str] = &[
+        include_str!("fixtures/v0.json"),
+        include_str!("fixtures/v1.json"),
+        include_str!("fixtures/v2.json"),
+        include_str!("fixtures/v3.json"),
+        include_str!("fixtures/v4.json"),
+        include_str!("fixtures/v5.json"),
+        include_str!("fixtures/v6.json"),
+        include_str!("fixtures/v7.json"),
+        include_str!("fixtures/v8.json"),
+        include_str!("fixtures/v9.json"),
+        include_str!("fixtures/v10.json"),
+        include_str!("fixtures/v11.json"),
+    ];
+
+    /// Serializes the world into a save file at [`SAVE_VERSION`].
+    fn save_json(
+        world: &World,
+        map: &Map,
+        levels: &BTreeMap<i32, Map>,
+        game_log: Vec<String>,
+    ) -> String {
+        let type_registry = type_registry();
+        let scene = DynamicScene::from_world(world, &type_registry);
+        serde_json::to_string(&SaveData {
+            version: SAVE_VERSION,
+            metadata: SaveMetadata {
+                character_name: "Player".to_string(),
+                depth: map.depth,
+                hp: 100,
+                max_hp: 100,
+                turn: 0,
+                timestamp: timestamp_now(),
+                run_id: 0,
+                permadeath: false,
+            },
+            seed: 0,
+            map,
+            levels,
+            game_log,
+            kills: &BTreeMap::new(),
+            scene: SceneSerializer::new(&scene, &type_registry),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn migrations_cover_all_versions() {
+        assert_eq!(migrations::MIGRATIONS.len(), SAVE_VERSION as usize);
+        assert_eq!(FIXTURES.len(), SAVE_VERSION as usize + 1);
+    }
+
+    #[test]
+    fn load_all_fixtures() {
+        for (version, data) in FIXTURES.iter().enumerate() {
+            let raw = serde_json::from_str(data).unwrap();
+            assert_eq!(save_version(&raw).unwrap(), version as u32);
+
+            let save = SaveFile::from_json(data)
+                .unwrap_or_else(|err| panic!("could not load v{} save: {}", version, err));
+            assert_eq!(save.version, SAVE_VERSION);
+
+            let mut world = load_world(&save);
+            check_fixture_entities(&mut world);
+        }
+    }
+
+    #[test]
+    fn format_timestamp_as_utc_date() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00");
+        assert_eq!(format_timestamp(1791331200), "2026-10-07 00:00");
+        assert_eq!(format_timestamp(1791331200 + 3600 + 60), "2026-10-07 01:01");
+    }
+
+    #[test]
+    fn permadeath_saves_only_into_active_slot() {
+        let run = Run {
+            id: 7,
+            permadeath: false,
+        };
+        assert!(run.can_save_into(1, ActiveSlot(Some(0))));
+
+        let run = Run {
+            id: 7,
+            permadeath: true,
+        };
+        assert!(run.can_save_into(0, ActiveSlot(Some(0))));
+        assert!(!run.can_save_into(1, ActiveSlot(Some(0))));
+        // The game was never saved or its slot was deleted.
+        assert!(run.can_save_into(1, ActiveSlot(None)));
+    }
+
+    #[test]
+    fn save_and_load_inventory() {
+        let mut world = World::new();
+        let player = world
+            .spawn()
+            .insert_bundle((Player, Energy::new(NORMAL_SPEED)))
+            .id();
+        world.spawn().insert_bundle((
+            Item,
+            EntityName {
+                name: "Health Potion".to_string(),
+            },
+            Owned { owner: player },
+        ));
+        world.spawn().insert_bundle((
+            Item,
+            EntityName {
+                name: "Dagger".to_string(),
+            },
+            Equipped {
+                by: player,
+                slot: EquipmentSlot::Melee,
+            },
+        ));
+        world.spawn().insert_bundle((
+            Mob,
+            Movement::new(MovementMode::Chase),
+            Energy::new(NORMAL_SPEED),
+        ));
+
+        let data = save_json(&world, &Map::new(10, 10, 1), &BTreeMap::new(), Vec::new());
+        let mut world = load_world(&SaveFile::from_json(&data).unwrap());
+        check_fixture_entities(&mut world);
+    }
+
+    #[test]
+    fn save_and_load_map() {
+        let mut map = Map::new(5, 4, 1);
+        map.tiles[6] = Tile::Floor;
+        map.revealed_tiles[6] = true;
+        map.bloodstains.insert(7);
+        let levels = BTreeMap::from([(2, Map::new(5, 4, 2))]);
+        let game_log = vec!["Welcome to Tailarc!".to_string()];
+
+        let data = save_json(&World::new(), &map, &levels, game_log.clone());
+        let save = SaveFile::from_json(&data).unwrap();
+        let loaded = save.map.as_ref().unwrap();
+        assert_eq!((loaded.width, loaded.height, loaded.depth), (5, 4, 1));
+        assert_eq!(loaded.tiles[6], Tile::Floor);
+        assert!(loaded.revealed_tiles[6]);
+        assert!(loaded.bloodstains.contains(&7));
+        assert_eq!(save.levels.keys().collect::<Vec<_>>(), vec![&2]);
+        assert_eq!(save.levels[&2].depth, 2);
+        assert_eq!(save.game_log, game_log);
+    }
+
+    #[test]
+    fn reject_newer_version() {
+        let data = format!(
+            r#"{{"version": {}, "metadata": null, "map": null, "game_log": [], "kills": {{}}, "scene": []}}"#,
+            SAVE_VERSION + 1
+        );
+        assert!(matches!(
+            SaveFile::from_json(&data),
+            Err(LoadError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[test]
+    fn autosave_every_interval_and_on_depth_change() {
+        let autosave = Autosave {
+            interval: 50,
+            enabled: true,
+            last_save: Some((100, 1)),
+        };
+        assert!(!autosave.is_due(149, 1));
+        assert!(autosave.is_due(150, 1));
+        assert!(autosave.is_due(101, 2));
+
+        let disabled = Autosave {
+            interval: 0,
+            enabled: true,
+            last_save: Some((100, 1)),
+        };
+        assert!(!disabled.is_due(1000, 1));
+        assert!(disabled.is_due(101, 2));
+    }
+}
@@ -0,0 +1,123 @@
+//! Actions that entities fail to do.
+
+use std::fmt;
+
+use bevy_ecs::prelude::*;
+
+use crate::components::Owned;
+
+/// Why an action of an entity was rejected.
+///
+/// Rejected actions do not spend [energy](crate::components::Energy), so the turn of the entity is
+/// refunded. Intents can be stale, e.g. when an item is destroyed during the same tick as it is
+/// used, so they must be checked rather than trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionRejection {
+    /// The target of the action is not an item, or does not exist anymore.
+    NotAnItem,
+    /// The item is not in the inventory of the entity.
+    NotOwned,
+    /// The item can be neither consumed nor equipped.
+    NotUsable,
+    /// The entity has no [`Position`](crate::components::Position) on the current level, so there
+    /// is nowhere to do the action.
+    NotOnMap,
+}
+
+impl fmt::Display for ActionRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            ActionRejection::NotAnItem => "There is no such item",
+            ActionRejection::NotOwned => "You do not have that item",
+            ActionRejection::NotUsable => "You cannot use that item",
+            ActionRejection::NotOnMap => "You are not on the map",
+        };
+        f.write_str(message)
+    }
+}
+
+/// Checks that an item is in the inventory of `owner`, given the [`Owned`] component of the item.
+pub fn check_owner(owner: Entity, owned: Option<&Owned>) -> Result<(), ActionRejection> {
+    match owned {
+        Some(owned) if owned.owner == owner => Ok(()),
+        _ => Err(ActionRejection::NotOwned),
+    }
+}
+
+/// Helpers for testing the systems that carry out actions.
+#[cfg(test)]
+pub mod test_utils {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::components::{
+        ConsumableEffects, Energy, EntityName, Item, Player, Position, WantsToDropItem,
+        WantsToUseItem, ACTION_COST, NORMAL_SPEED,
+    };
+    use crate::gamelog::GameLog;
+    use crate::map::Map;
+    use crate::rng::GameRng;
+    use crate::systems::particle::ParticleBuilder;
+
+    /// Returns a world with the resources used by the item systems, and its player.
+    pub fn world_with_player() -> (World, Entity) {
+        let mut world = World::new();
+        world.insert_resource(GameLog {
+            entries: Mutex::new(Vec::new()),
+        });
+        world.insert_resource(Map::new(10, 10, 1));
+        world.insert_resource(GameRng::new(0));
+        world.insert_resource(ParticleBuilder::new());
+
+        let player = world
+            .spawn()
+            .insert_bundle((
+                Player,
+                EntityName {
+                    name: "Player".to_string(),
+                },
+                Position { x: 1, y: 1 },
+                Energy::new(NORMAL_SPEED),
+            ))
+            .id();
+        (world, player)
+    }
+
+    pub fn spawn_potion(world: &mut World, owned: Option<Owned>) -> Entity {
+        let mut potion = world.spawn();
+        potion.insert_bundle((
+            Item,
+            EntityName {
+                name: "Health Potion".to_string(),
+            },
+            ConsumableEffects {
+                heal: Some(10),
+                ..Default::default()
+            },
+        ));
+        if let Some(owned) = owned {
+            potion.insert(owned);
+        }
+        potion.id()
+    }
+
+    pub fn run_system<Params>(world: &mut World, system: impl IntoSystem<(), (), Params>) {
+        let mut stage = SystemStage::single_threaded();
+        stage.add_system(system);
+        stage.run(world);
+    }
+
+    /// Checks that the action of the player was rejected and did not take any time.
+    pub fn assert_rejected(world: &World, player: Entity, rejection: ActionRejection) {
+        let entries = world
+            .get_resource::<GameLog>()
+            .unwrap()
+            .entries
+            .lock()
+            .unwrap();
+        assert_eq!(entries.last(), Some(&rejection.to_string()));
+        assert_eq!(world.get::<Energy>(player).unwrap().energy, ACTION_COST);
+        assert!(world.get::<WantsToUseItem>(player).is_none());
+        assert!(world.get::<WantsToDropItem>(player).is_none());
+    }
+}
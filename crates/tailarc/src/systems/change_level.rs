@@ -0,0 +1,193 @@
+//! Moving between levels.
+
+use bevy_ecs::prelude::*;
+
+use crate::components::{OtherLevelPosition, ParticleLifetime, Player, Position, Viewshed};
+use crate::gamelog::GameLog;
+use crate::map::{LevelStore, Map, Tile, MAP_HEIGHT, MAP_WIDTH};
+use crate::map_builders::level_builder;
+use crate::rng::GameRng;
+use crate::RunState;
+
+/// Moves the player one level down ([`RunState::NextLevel`]) or up ([`RunState::PreviousLevel`]).
+///
+/// The current level and its entities are frozen into the [`LevelStore`]: the [`Position`] of the
+/// entities is replaced by an [`OtherLevelPosition`]. The player and the items that they carry are
+/// not part of any level. If the player has already visited the target level, it is restored as
+/// it was left. Otherwise, a new level is generated.
+pub fn change_level_system(
+    mut commands: Commands,
+    mut state: ResMut<State<RunState>>,
+    mut map: ResMut<Map>,
+    mut level_store: ResMut<LevelStore>,
+    game_log: Res<GameLog>,
+    rng: Res<GameRng>,
+    mut player: Query<(&mut Position, &mut Viewshed), With<Player>>,
+    on_level: Query<(Entity, &Position, Option<&ParticleLifetime>), Without<Player>>,
+    mut other_levels: Query<(Entity, &OtherLevelPosition, Option<&mut Viewshed>), Without<Player>>,
+) {
+    let (mut player_pos, mut player_viewshed) = player.single_mut();
+    let descending = *state.current() == RunState::NextLevel;
+    let depth = map.depth;
+    let target_depth = if descending { depth + 1 } else { depth - 1 };
+
+    // Freeze the current level.
+    for (entity, pos, particle) in on_level.iter() {
+        if particle.is_some() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        commands
+            .entity(entity)
+            .remove::<Position>()
+            .insert(OtherLevelPosition {
+                x: pos.x,
+                y: pos.y,
+                depth,
+            });
+    }
+
+    let new_map = match level_store.levels.remove(&target_depth) {
+        Some(mut target_map) => {
+            // Thaw the level that the player comes back to.
+            for (entity, other_level_pos, viewshed) in other_levels.iter_mut() {
+                if other_level_pos.depth != target_depth {
+                    continue;
+                }
+                commands
+                    .entity(entity)
+                    .remove::<OtherLevelPosition>()
+                    .insert(Position {
+                        x: other_level_pos.x,
+                        y: other_level_pos.y,
+                    });
+                if let Some(mut viewshed) = viewshed {
+                    viewshed.dirty = true;
+                }
+            }
+
+            // Arrive on the stairs leading back to the level the player comes from.
+            let stairs = if descending {
+                Tile::UpStairs
+            } else {
+                Tile::DownStairs
+            };
+            let idx = target_map
+                .find_tile(stairs)
+                .expect("visited level should have stairs");
+            *player_pos = Position {
+                x: idx as u32 % target_map.width,
+                y: idx as u32 / target_map.width,
+            };
+
+            target_map.reset_indexes();
+            target_map
+        }
+        None => {
+            let mut builder = level_builder(MAP_WIDTH, MAP_HEIGHT, target_depth, rng.seed());
+            let new_map = builder.build_map();
+            builder.spawn_entities(&mut commands);
+            *player_pos = builder.starting_position();
+            new_map
+        }
+    };
+    let old_map = std::mem::replace(&mut *map, new_map);
+    level_store.levels.insert(depth, old_map);
+    player_viewshed.dirty = true;
+
+    if descending {
+        game_log.add_entry(format!("You descend to depth {}", target_depth));
+    } else {
+        game_log.add_entry(format!("You climb up to depth {}", target_depth));
+    }
+    state.set(RunState::AwaitingInput).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::raws::load_raws;
+
+    fn change_level(world: &mut World, state: RunState) {
+        world.insert_resource(State::new(state));
+        let mut stage = SystemStage::single_threaded();
+        stage.add_system(change_level_system);
+        stage.run(world);
+    }
+
+    fn player_position(world: &mut World) -> Position {
+        *world
+            .query_filtered::<&Position, With<Player>>()
+            .iter(world)
+            .next()
+            .unwrap()
+    }
+
+    /// Returns the position of the tile of the current map.
+    fn tile_position(world: &World, tile: Tile) -> Position {
+        let map = world.get_resource::<Map>().unwrap();
+        let idx = map.find_tile(tile).unwrap() as u32;
+        Position {
+            x: idx % map.width,
+            y: idx / map.width,
+        }
+    }
+
+    #[test]
+    fn go_down_and_back_up() {
+        load_raws(None).unwrap();
+        let mut world = World::new();
+        world.insert_resource(GameLog {
+            entries: Mutex::new(Vec::new()),
+        });
+        world.insert_resource(GameRng::new(0));
+        world.insert_resource(LevelStore::default());
+
+        let mut map = Map::new(MAP_WIDTH, MAP_HEIGHT, 1);
+        let stairs = map.xy_idx(2, 2);
+        map.tiles[stairs] = Tile::DownStairs;
+        map.revealed_tiles[5] = true;
+        map.bloodstains.insert(6);
+        world.insert_resource(map);
+
+        world
+            .spawn()
+            .insert_bundle((Player, Position { x: 2, y: 2 }, Viewshed::new(8)));
+        let mob = world.spawn().insert(Position { x: 3, y: 4 }).id();
+
+        change_level(&mut world, RunState::NextLevel);
+        assert_eq!(world.get_resource::<Map>().unwrap().depth, 2);
+        assert!(world.get::<Position>(mob).is_none());
+        let other_level_pos = world.get::<OtherLevelPosition>(mob).unwrap();
+        assert_eq!(
+            (other_level_pos.x, other_level_pos.y, other_level_pos.depth),
+            (3, 4, 1)
+        );
+        let up_stairs = tile_position(&world, Tile::UpStairs);
+        assert_eq!(player_position(&mut world), up_stairs);
+        let level = &world.get_resource::<LevelStore>().unwrap().levels[&1];
+        assert!(level.revealed_tiles[5]);
+        assert!(level.bloodstains.contains(&6));
+
+        change_level(&mut world, RunState::PreviousLevel);
+        let map = world.get_resource::<Map>().unwrap();
+        assert_eq!(map.depth, 1);
+        assert!(map.revealed_tiles[5]);
+        assert!(map.bloodstains.contains(&6));
+        assert_eq!(
+            *world.get::<Position>(mob).unwrap(),
+            Position { x: 3, y: 4 }
+        );
+        assert!(world.get::<OtherLevelPosition>(mob).is_none());
+        assert_eq!(player_position(&mut world), Position { x: 2, y: 2 });
+        let level_store = world.get_resource::<LevelStore>().unwrap();
+        assert_eq!(level_store.levels.keys().collect::<Vec<_>>(), vec![&2]);
+
+        // The level below was kept as it was left.
+        change_level(&mut world, RunState::NextLevel);
+        assert_eq!(tile_position(&world, Tile::UpStairs), up_stairs);
+        assert_eq!(player_position(&mut world), up_stairs);
+    }
+}
@@ -3,6 +3,8 @@ use bevy_ecs::prelude::*;
 use crate::components::{CanSufferDamage, CombatStats, EntityName, Player, Position};
 use crate::gamelog::GameLog;
 use crate::map::Map;
+use crate::morgue::KillCounts;
+use crate::save::{delete_slot, ActiveSlot, Run, SaveSlots};
 use crate::RunState;
 
 pub fn damage_system(
@@ -11,9 +13,10 @@ pub fn damage_system(
 ) {
     for (mut stats, mut can_suffer_damage, pos) in q.iter_mut() {
         let dmg = can_suffer_damage.amount.iter().sum::<i32>();
-        stats.hp -= dmg;
+        // Negative damage heals, e.g. from regeneration.
+        stats.hp = i32::min(stats.max_hp, stats.hp - dmg);
 
-        if dmg != 0 {
+        if dmg > 0 {
             // Add bloodstains.
             if let Some(pos) = pos {
                 let idx = map.xy_idx(pos.x as u32, pos.y as u32);
@@ -28,23 +31,53 @@ pub fn damage_system(
 
 /// Despawns the entities that have been killed.
 ///
-/// If the player has been killed, updates the RunState to [`RunState::MainMenu`].
+/// If the player has been killed, updates the RunState to [`RunState::GameOver`]. In permadeath
+/// mode (see [`Run`]), all the save files of the run are deleted. Monsters are only counted in the
+/// [`KillCounts`] if they were killed by the player.
 pub fn delete_the_dead(
     mut commands: Commands,
     mut state: ResMut<State<RunState>>,
+    mut active_slot: ResMut<ActiveSlot>,
+    mut kill_counts: ResMut<KillCounts>,
+    run: Res<Run>,
     game_log: Res<GameLog>,
-    q: Query<(Entity, &CombatStats, Option<&EntityName>, Option<&Player>)>,
+    q: Query<(
+        Entity,
+        &CombatStats,
+        Option<&CanSufferDamage>,
+        Option<&EntityName>,
+        Option<&Player>,
+    )>,
 ) {
-    for (entity, stats, name, player) in q.iter() {
+    let player_name = q
+        .iter()
+        .find(|(.., player)| player.is_some())
+        .and_then(|(.., name, _)| name)
+        .map(|name| name.name.as_str());
+
+    for (entity, stats, can_suffer_damage, name, player) in q.iter() {
         if stats.hp <= 0 {
             if player.is_some() {
                 // Player died.
                 game_log.add_entry("You died! :(");
-                state.overwrite_replace(RunState::MainMenu).unwrap();
+                if run.permadeath {
+                    // The run may have been saved into other slots before being loaded, so look
+                    // for its save files instead of only deleting the active slot.
+                    active_slot.0 = None;
+                    for slot in SaveSlots::read().slots_of_run(run.id) {
+                        delete_slot(slot);
+                    }
+                }
+                state.overwrite_replace(RunState::GameOver).unwrap();
             } else {
                 // A monster died.
                 if let Some(name) = name {
                     game_log.add_entry(format!("{} is dead", name.name));
+                    let killer =
+                        can_suffer_damage.and_then(|damage| damage.last_attacker.as_deref());
+                    if killer.is_some() && killer == player_name {
+                        kill_counts.add(&name.name);
+                    }
                 } else {
                     // Silent death...
                 }
@@ -1,38 +1,125 @@
 use bevy_ecs::prelude::*;
 
-use crate::components::{EntityName, Item, Owned, Player, Position, WantsToDropItem};
+use crate::components::{
+    Energy, EntityName, Item, Owned, Player, Position, WantsToDropItem, DROP_COST,
+};
 use crate::gamelog::GameLog;
 
+use super::action::{check_owner, ActionRejection};
+
 /// Processes all the [`WantsToDropItem`] components and removes them from the entities.
+///
+/// Items that cannot be dropped are rejected with an [`ActionRejection`].
 pub fn drop_item_system(
     mut commands: Commands,
     game_log: Res<GameLog>,
-    mut wants_drop: Query<(Entity, &WantsToDropItem, &Position)>,
-    items: Query<(Entity, &EntityName, &Owned), With<Item>>,
+    mut wants_drop: Query<(Entity, &WantsToDropItem, Option<&Position>)>,
+    items: Query<(&EntityName, Option<&Owned>), With<Item>>,
+    mut energy: Query<&mut Energy>,
     player: Query<Entity, With<Player>>,
 ) {
     let player_entity = player.single();
 
     for (entity, wants_drop, pos) in wants_drop.iter_mut() {
-        let (item, name, owned) = items
-            .get(wants_drop.item)
-            .expect("cannot drop something that is not an item");
+        // Remove WantsToDropItem component from entity to prevent dropping the item twice.
+        commands.entity(entity).remove::<WantsToDropItem>();
 
-        // Make sure that owned matches with the entity that wants to use it.
-        if owned.owner != entity {
-            panic!("cannot drop something that you do not own");
-        }
+        // Make sure that the item is owned by the entity that wants to drop it, and that the entity
+        // is on the map to drop it there.
+        let item = wants_drop.item;
+        let checked = items
+            .get(item)
+            .map_err(|_| ActionRejection::NotAnItem)
+            .and_then(|(name, owned)| check_owner(entity, owned).map(|()| name))
+            .and_then(|name| pos.map(|&pos| (name, pos)).ok_or(ActionRejection::NotOnMap));
+        let (name, pos) = match checked {
+            Ok(checked) => checked,
+            Err(rejection) => {
+                if entity == player_entity {
+                    game_log.add_entry(rejection.to_string());
+                }
+                continue;
+            }
+        };
 
         // Drop the item by removing Owner and adding a Position.
         commands.entity(item).remove::<Owned>();
-        commands.entity(item).insert(*pos);
+        commands.entity(item).insert(pos);
+        if let Ok(mut energy) = energy.get_mut(entity) {
+            energy.energy -= DROP_COST;
+        }
 
         // Display message if player.
         if entity == player_entity {
             game_log.add_entry(format!("You drop the {}", name.name));
         }
+    }
+}
 
-        // Remove WantsToDropItem component from entity to prevent dropping the item twice.
-        commands.entity(entity).remove::<WantsToDropItem>();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::action::test_utils::{
+        assert_rejected, run_system, spawn_potion, world_with_player,
+    };
+
+    #[test]
+    fn drop_destroyed_item() {
+        let (mut world, player) = world_with_player();
+        let potion = spawn_potion(&mut world, Some(Owned { owner: player }));
+        world.despawn(potion);
+        world
+            .entity_mut(player)
+            .insert(WantsToDropItem { item: potion });
+
+        run_system(&mut world, drop_item_system);
+        assert_rejected(&world, player, ActionRejection::NotAnItem);
+    }
+
+    #[test]
+    fn drop_item_off_the_map() {
+        let (mut world, player) = world_with_player();
+        world.entity_mut(player).remove::<Position>();
+        let potion = spawn_potion(&mut world, Some(Owned { owner: player }));
+        world
+            .entity_mut(player)
+            .insert(WantsToDropItem { item: potion });
+
+        run_system(&mut world, drop_item_system);
+        assert_rejected(&world, player, ActionRejection::NotOnMap);
+        assert_eq!(
+            world.get::<Owned>(potion).map(|owned| owned.owner),
+            Some(player)
+        );
+        assert!(world.get::<Position>(potion).is_none());
+    }
+
+    #[test]
+    fn drop_item_on_the_floor() {
+        let (mut world, player) = world_with_player();
+        let potion = spawn_potion(&mut world, None);
+        world.entity_mut(potion).insert(Position { x: 1, y: 1 });
+        world
+            .entity_mut(player)
+            .insert(WantsToDropItem { item: potion });
+
+        run_system(&mut world, drop_item_system);
+        assert_rejected(&world, player, ActionRejection::NotOwned);
+    }
+
+    #[test]
+    fn drop_owned_item() {
+        let (mut world, player) = world_with_player();
+        let potion = spawn_potion(&mut world, Some(Owned { owner: player }));
+        world
+            .entity_mut(player)
+            .insert(WantsToDropItem { item: potion });
+
+        run_system(&mut world, drop_item_system);
+        assert!(world.get::<Owned>(potion).is_none());
+        assert_eq!(
+            world.get::<Position>(potion),
+            Some(&Position { x: 1, y: 1 })
+        );
     }
 }
@@ -0,0 +1,80 @@
+//! Energy of the entities that take turns.
+
+use bevy_ecs::prelude::*;
+
+use crate::components::{Energy, Position, StatusEffects, ACTION_COST};
+
+/// Gives energy to the entities on the current level at the end of every turn.
+pub fn energy_system(mut q: Query<(&mut Energy, Option<&StatusEffects>), With<Position>>) {
+    for (mut energy, status_effects) in q.iter_mut() {
+        let speed = status_effects.map_or(energy.speed, |effects| effects.speed(energy.speed));
+        // Monsters end their turn when they attack. Do not let the energy that they did not use
+        // pile up.
+        energy.energy = i32::min(energy.energy + speed, speed + ACTION_COST);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::{StatusEffect, StatusEffectKind, NORMAL_SPEED};
+
+    fn spawn(world: &mut World, energy: i32, effect: Option<StatusEffectKind>) -> Entity {
+        let mut status_effects = StatusEffects::default();
+        if let Some(kind) = effect {
+            status_effects.add(StatusEffect { kind, turns: 10 });
+        }
+        world
+            .spawn()
+            .insert_bundle((
+                Position { x: 1, y: 1 },
+                Energy {
+                    energy,
+                    speed: NORMAL_SPEED,
+                },
+                status_effects,
+            ))
+            .id()
+    }
+
+    fn energy(world: &World, entity: Entity) -> i32 {
+        world.get::<Energy>(entity).unwrap().energy
+    }
+
+    #[test]
+    fn haste_and_slow_change_the_speed() {
+        let mut world = World::new();
+        let normal = spawn(&mut world, 0, None);
+        let hasted = spawn(&mut world, 0, Some(StatusEffectKind::Haste));
+        let slowed = spawn(&mut world, 0, Some(StatusEffectKind::Slow));
+
+        let mut stage = SystemStage::single_threaded();
+        stage.add_system(energy_system);
+        stage.run(&mut world);
+        assert_eq!(energy(&world, normal), 100);
+        assert_eq!(energy(&world, hasted), 150);
+        assert_eq!(energy(&world, slowed), 50);
+    }
+
+    #[test]
+    fn clamp_unused_energy() {
+        let mut world = World::new();
+        let normal = spawn(&mut world, 150, None);
+        let hasted = spawn(&mut world, 150, Some(StatusEffectKind::Haste));
+        let off_the_map = world
+            .spawn()
+            .insert(Energy {
+                energy: 150,
+                speed: NORMAL_SPEED,
+            })
+            .id();
+
+        let mut stage = SystemStage::single_threaded();
+        stage.add_system(energy_system);
+        stage.run(&mut world);
+        assert_eq!(energy(&world, normal), NORMAL_SPEED + ACTION_COST);
+        assert_eq!(energy(&world, hasted), 150 + ACTION_COST);
+        // Entities on other levels do not gain energy.
+        assert_eq!(energy(&world, off_the_map), 150);
+    }
+}
@@ -1,9 +1,15 @@
 use bevy_ecs::prelude::*;
 use bracket_lib::prelude::*;
+use rand::Rng;
 
-use crate::components::{CombatStats, Item, Mob, Player, Position, Viewshed, WantsToMelee};
+use crate::components::{
+    CombatStats, Energy, Item, Mob, Player, Position, StatusEffectKind, StatusEffects, Viewshed,
+    WantsToMelee, ACTION_COST,
+};
 use crate::gamelog::GameLog;
-use crate::map::Map;
+use crate::map::{Map, Tile};
+use crate::rng::GameRng;
+use crate::save::SaveSlots;
 use crate::RunState;
 
 use super::inventory::pickup_item;
@@ -16,12 +22,36 @@ pub fn player_input_system(
     bterm: Res<BTerm>,
     map: Res<Map>,
     game_log: Res<GameLog>,
+    mut rng: ResMut<GameRng>,
+    mut save_slots: ResMut<SaveSlots>,
     mut state: ResMut<State<RunState>>,
-    mut player: Query<(Entity, &mut Position, &mut Viewshed, &CombatStats), With<Player>>,
+    mut player: Query<
+        (
+            Entity,
+            &mut Position,
+            &mut Viewshed,
+            &CombatStats,
+            &mut Energy,
+            Option<&StatusEffects>,
+        ),
+        With<Player>,
+    >,
     enemies: Query<(Entity, &CombatStats), With<Mob>>,
     items: Query<(Entity, &Item)>,
 ) {
-    let (player_entity, mut player_pos, mut viewshed, _combat_stats) = player.single_mut();
+    let (player_entity, mut player_pos, mut viewshed, _combat_stats, mut energy, status_effects) =
+        player.single_mut();
+
+    // Climb up stairs ('<').
+    if bterm.key == Some(VirtualKeyCode::Comma) && bterm.shift {
+        let idx = map.xy_idx(player_pos.x, player_pos.y);
+        if map.tiles[idx] == Tile::UpStairs {
+            state.set(RunState::PreviousLevel).unwrap();
+        } else {
+            game_log.add_entry("There is no way up from here");
+        }
+        return;
+    }
 
     // Pickup item.
     if bterm.key == Some(VirtualKeyCode::Comma) {
@@ -34,6 +64,7 @@ pub fn player_input_system(
             items,
         );
         // Picking up items is a turn.
+        energy.energy -= ACTION_COST;
         RunState::advance_state(&mut state);
         return;
     }
@@ -45,6 +76,13 @@ pub fn player_input_system(
         return;
     }
 
+    // Show character sheet.
+    if bterm.key == Some(VirtualKeyCode::C) {
+        // We can unwrap() here because this system is only executed during RunState::AwaitingInput.
+        state.set(RunState::ShowCharacterSheet).unwrap();
+        return;
+    }
+
     // Show drop selection.
     if bterm.key == Some(VirtualKeyCode::D) {
         // We can unwrap() here because this system is only executed during RunState::AwaitingInput.
@@ -52,10 +90,22 @@ pub fn player_input_system(
         return;
     }
 
+    // Descend stairs.
+    if bterm.key == Some(VirtualKeyCode::Period) {
+        let idx = map.xy_idx(player_pos.x, player_pos.y);
+        if map.tiles[idx] == Tile::DownStairs {
+            state.set(RunState::NextLevel).unwrap();
+        } else {
+            game_log.add_entry("There is no way down from here");
+        }
+        return;
+    }
+
     // Save game.
     if bterm.key == Some(VirtualKeyCode::Escape) {
+        *save_slots = SaveSlots::read();
         // We can unwrap() here because this system is only executed during RunState::AwaitingInput.
-        state.set(RunState::SaveGame).unwrap();
+        state.set(RunState::ShowSaveSlots).unwrap();
         return;
     }
 
@@ -92,6 +142,18 @@ pub fn player_input_system(
 
     // If we have a delta, input has been received.
     if (delta_x, delta_y) != (0, 0) {
+        // A confused player moves in a random direction.
+        if matches!(status_effects, Some(effects) if effects.has(StatusEffectKind::Confusion)) {
+            loop {
+                delta_x = rng.gen_range(-1..=1);
+                delta_y = rng.gen_range(-1..=1);
+                if (delta_x, delta_y) != (0, 0) {
+                    break;
+                }
+            }
+            game_log.add_entry("You stumble around in confusion");
+        }
+
         // Calculate the new position.
         let mut new_position = *player_pos;
         new_position.x = (new_position.x as i32 + delta_x)
@@ -121,6 +183,7 @@ pub fn player_input_system(
             }
         }
 
+        energy.energy -= ACTION_COST;
         RunState::advance_state(&mut state);
     }
 }
@@ -0,0 +1,116 @@
+//! Game loading systems.
+
+use std::fs;
+use std::sync::Mutex;
+
+use bevy_ecs::entity::EntityMap;
+use bevy_ecs::prelude::*;
+use bevy_reflect::TypeRegistryArc;
+
+use crate::components::{register_component_types, Viewshed};
+use crate::gamelog::GameLog;
+use crate::map::{LevelStore, Map};
+use crate::morgue::KillCounts;
+use crate::rng::GameRng;
+use crate::save::{delete_slot, slot_path, ActiveSlot, Autosave, LoadError, Run, SaveFile};
+use crate::{RunState, TurnCounter};
+
+/// Loads the game written by [`save_game_system`](super::save_game::save_game_system) in the
+/// [`ActiveSlot`] and resumes it.
+///
+/// All the entities that are currently in the [`World`] are replaced by the entities in the save
+/// file and the [`Map`] and [`GameLog`] resources are replaced by the saved ones. If the save file
+/// cannot be loaded, the game goes back to the [`RunState::MainMenu`].
+///
+/// In permadeath mode (see [`Run`]), the save file is deleted once it is loaded.
+pub fn load_game_system(world: &mut World) {
+    let slot = world
+        .get_resource::<ActiveSlot>()
+        .unwrap()
+        .0
+        .expect("no save slot selected");
+    let save = match load_entities(world, slot) {
+        Ok(save) => save,
+        Err(err) => {
+            tracing::error!("could not load save data: {}", err);
+            let mut state = world.get_resource_mut::<State<RunState>>().unwrap();
+            state.overwrite_replace(RunState::MainMenu).unwrap();
+            return;
+        }
+    };
+
+    let run = Run {
+        id: save.metadata.run_id,
+        permadeath: save.metadata.permadeath,
+    };
+    if run.permadeath {
+        // The save file is written again when the game is saved.
+        delete_slot(slot);
+    }
+    world.insert_resource(run);
+
+    // Rebuild the parts of the map that are not saved. `tile_content` and `blocked` are refreshed
+    // by the indexing systems and the visible tiles by the visibility system.
+    //
+    // Old save files do not contain the map. In that case, the current map is kept.
+    match save.map {
+        Some(mut map) => {
+            map.reset_indexes();
+            world.insert_resource(map);
+        }
+        None => world.get_resource_mut::<Map>().unwrap().reset_indexes(),
+    }
+    for mut viewshed in world.query::<&mut Viewshed>().iter_mut(world) {
+        viewshed.dirty = true;
+    }
+
+    world.insert_resource(TurnCounter {
+        turn: save.metadata.turn,
+    });
+    // Count the turns until the next autosave from the loaded turn. A permadeath game is not
+    // autosaved, otherwise its consumed save file would be written again.
+    let mut autosave = world.get_resource_mut::<Autosave>().unwrap();
+    autosave.enabled = !run.permadeath;
+    autosave.last_save = Some((save.metadata.turn, save.metadata.depth));
+
+    world.insert_resource(LevelStore {
+        levels: save.levels,
+    });
+    world.insert_resource(KillCounts { kills: save.kills });
+    // The state of the gameplay RNG is not saved, only the seed of the game. Old save files do not
+    // contain the seed so a new one is picked for the levels that have not been generated yet.
+    world.insert_resource(match save.seed {
+        Some(seed) => GameRng::resume(seed, save.metadata.turn),
+        None => GameRng::from_entropy(),
+    });
+
+    let game_log = GameLog {
+        entries: Mutex::new(save.game_log),
+    };
+    game_log.add_entry("Welcome back to Tailarc!");
+    world.insert_resource(game_log);
+
+    let mut state = world.get_resource_mut::<State<RunState>>().unwrap();
+    state.set(RunState::AwaitingInput).unwrap();
+}
+
+/// Reads the save file in the given slot and replaces the entities of the [`World`] by the saved
+/// ones.
+fn load_entities(world: &mut World, slot: usize) -> Result<SaveFile, LoadError> {
+    let type_registry = world.get_resource::<TypeRegistryArc>().unwrap().clone();
+    register_component_types(&mut type_registry.write());
+
+    let save = SaveFile::from_json(&fs::read_to_string(slot_path(slot))?)?;
+    let scene = save.deserialize_scene(&type_registry)?;
+
+    // Remove the entities of the current game before spawning the saved ones.
+    let entities: Vec<Entity> = world.query::<Entity>().iter(world).collect();
+    for entity in entities {
+        world.despawn(entity);
+    }
+
+    // Entity references (e.g. `Owned.owner` and `Equipped.by`) are remapped to the newly spawned
+    // entities by `write_to_world`.
+    scene.write_to_world(world, &mut EntityMap::default())?;
+    Ok(save)
+}
@@ -2,21 +2,33 @@ use std::time::Duration;
 
 use bevy_ecs::prelude::*;
 use bracket_lib::prelude::*;
+use rand::Rng;
 
 use crate::components::{
-    CanSufferDamage, CombatStats, EntityName, Equipped, ItemStats, Position, Renderable,
-    WantsToMelee,
+    Attributes, CanSufferDamage, CombatStats, EntityName, Equipped, ItemStats, Position,
+    Renderable, WantsToMelee,
 };
 use crate::gamelog::GameLog;
+use crate::rng::GameRng;
 
 use super::particle::ParticleBuilder;
 
+/// Minimum value of `1d20 + to-hit bonus` for a melee attack to hit.
+pub const TO_HIT_TARGET: i32 = 5;
+
 /// Processes all the [`WantsToMelee`] components and removes them from the entities.
 pub fn melee_combat_system(
     mut commands: Commands,
     game_log: Res<GameLog>,
+    mut rng: ResMut<GameRng>,
     mut particle_builder: ResMut<ParticleBuilder>,
-    wants_melee: Query<(Entity, &WantsToMelee, &EntityName, &CombatStats)>,
+    wants_melee: Query<(
+        Entity,
+        &WantsToMelee,
+        &EntityName,
+        &CombatStats,
+        Option<&Attributes>,
+    )>,
     mut target_stats: Query<(
         &CombatStats,
         &EntityName,
@@ -25,19 +37,33 @@ pub fn melee_combat_system(
     )>,
     equipped: Query<(&Equipped, &ItemStats)>,
 ) {
-    for (attacker, wants_melee, attacker_name, attacker_stats) in wants_melee.iter() {
+    for (attacker, wants_melee, attacker_name, attacker_stats, attacker_attributes) in
+        wants_melee.iter()
+    {
         let target = wants_melee.target;
+        let attacker_attributes = attacker_attributes.copied().unwrap_or_default();
 
         if let Ok((target_stats, target_name, mut can_suffer_damage, position)) =
             target_stats.get_mut(target)
         {
-            // Compute damage, taking into account equipped bonus.
+            let roll = rng.gen_range(1..=20) + attacker_attributes.to_hit_bonus();
+            if roll < TO_HIT_TARGET {
+                game_log.add_entry(format!(
+                    "{} misses {}",
+                    attacker_name.name, target_name.name
+                ));
+                commands.entity(attacker).remove::<WantsToMelee>();
+                continue;
+            }
+
+            // Compute damage, taking into account equipped and attribute bonuses.
             let attacker_power_bonus: i32 = equipped
                 .iter()
                 .filter(|(e, _)| e.by == attacker)
                 .map(|(_, s)| s.power)
                 .sum();
-            let attacker_power = attacker_stats.power + attacker_power_bonus;
+            let attacker_power =
+                attacker_stats.power + attacker_power_bonus + attacker_attributes.damage_bonus();
             let target_defense_bonus: i32 = equipped
                 .iter()
                 .filter(|(e, _)| e.by == target)
@@ -57,6 +83,7 @@ pub fn melee_combat_system(
                     attacker_name.name, target_name.name, damage
                 ));
                 can_suffer_damage.amount.push(damage);
+                can_suffer_damage.last_attacker = Some(attacker_name.name.clone());
                 if let Some(&position) = position {
                     particle_builder.request(
                         position,
@@ -1,13 +1,20 @@
 //! ECS systems.
 
+pub mod action;
+pub mod change_level;
 pub mod damage;
 pub mod drop_item;
+pub mod energy;
 pub mod input;
 pub mod inventory;
+pub mod load_game;
 pub mod map_indexing;
 pub mod melee_combat;
 pub mod monster_ai;
+pub mod new_game;
 pub mod particle;
+pub mod reload_raws;
 pub mod save_game;
+pub mod status_effects;
 pub mod use_item;
 pub mod visibility;
@@ -1,52 +1,201 @@
 use bevy_ecs::prelude::*;
 use bracket_lib::prelude::*;
+use rand::Rng;
 
-use crate::components::{EntityName, Mob, Player, Position, Viewshed, WantsToMelee};
+use crate::components::{
+    Energy, EntityName, Mob, Movement, MovementMode, Player, Position, StatusEffectKind,
+    StatusEffects, Viewshed, WantsToMelee, ACTION_COST,
+};
 use crate::map::Map;
+use crate::rng::GameRng;
+
+use super::visibility::update_viewshed;
 
 pub fn monster_ai_system(
     mut commands: Commands,
     mut map: ResMut<Map>,
+    mut rng: ResMut<GameRng>,
     mut set: QuerySet<(
         QueryState<(Entity, &Position), With<Player>>,
-        QueryState<(Entity, &mut Viewshed, &mut Position, &EntityName), With<Mob>>,
+        QueryState<
+            (
+                Entity,
+                &mut Viewshed,
+                &mut Position,
+                &mut Movement,
+                &mut Energy,
+                Option<&StatusEffects>,
+                &EntityName,
+            ),
+            With<Mob>,
+        >,
     )>,
 ) {
     let (player_entity, &player_pos) = set.q0().single();
 
-    for (entity, mut viewshed, mut pos, _name) in set.q1().iter_mut() {
-        if viewshed.visible_tiles.contains(&player_pos) {
-            let distance = DistanceAlg::Pythagoras.distance2d(
-                Point::new(pos.x, pos.y),
-                Point::new(player_pos.x, player_pos.y),
-            );
-            if distance < 1.5 {
-                // Within range. Attack the player!
-                commands.entity(entity).insert(WantsToMelee {
-                    target: player_entity,
-                });
-                continue;
+    for (entity, mut viewshed, mut pos, mut movement, mut energy, status_effects, _name) in
+        set.q1().iter_mut()
+    {
+        let confused =
+            matches!(status_effects, Some(effects) if effects.has(StatusEffectKind::Confusion));
+
+        // Fast monsters may act several times and slow monsters may not act at all.
+        while energy.can_act() {
+            energy.energy -= ACTION_COST;
+
+            let sees_player = viewshed.visible_tiles.contains(&player_pos);
+            if sees_player && !confused {
+                let distance = DistanceAlg::Pythagoras.distance2d(
+                    Point::new(pos.x, pos.y),
+                    Point::new(player_pos.x, player_pos.y),
+                );
+                if distance < 1.5 {
+                    // Within range. Attack the player!
+                    commands.entity(entity).insert(WantsToMelee {
+                        target: player_entity,
+                    });
+                    // Monsters attack at most once per turn.
+                    break;
+                }
             }
-            let path = a_star_search(
-                map.xy_idx(pos.x as u32, pos.y as u32),
-                map.xy_idx(player_pos.x as u32, player_pos.y as u32),
-                &*map,
-            );
-            if path.success && path.steps.len() > 1 {
-                // Move towards the player.
 
+            let destination = match movement.mode {
+                // Confused monsters stumble around.
+                _ if confused => random_step(&map, *pos, player_pos, &mut *rng),
+                MovementMode::Static => None,
+                MovementMode::Random => random_step(&map, *pos, player_pos, &mut *rng),
+                MovementMode::Wandering | MovementMode::Chase => {
+                    if sees_player {
+                        movement.last_known_player_position = Some(player_pos);
+                    } else if movement.last_known_player_position == Some(*pos) {
+                        // The player is not where they were last seen.
+                        movement.last_known_player_position = None;
+                    }
+
+                    match movement.last_known_player_position {
+                        Some(target) => step_towards(&map, *pos, target),
+                        None if movement.mode == MovementMode::Wandering => {
+                            random_step(&map, *pos, player_pos, &mut *rng)
+                        }
+                        None => None,
+                    }
+                }
+            };
+
+            if let Some(destination) = destination {
                 // Remove the old blocked state because the monster is moving out of that tile.
-                let old_idx = map.xy_idx(pos.x as u32, pos.y as u32);
+                let old_idx = map.xy_idx(pos.x, pos.y);
                 map.blocked[old_idx] = false;
 
-                pos.x = path.steps[1] as u32 % map.width;
-                pos.y = path.steps[1] as u32 / map.width;
-                viewshed.dirty = true;
+                *pos = destination;
 
                 // Set new blocked state because the monster is now in that tile.
-                let new_idx = map.xy_idx(pos.x, pos.y );
+                let new_idx = map.xy_idx(pos.x, pos.y);
                 map.blocked[new_idx] = true;
+
+                // Look around again before the next action.
+                update_viewshed(&mut viewshed, *pos, &map);
             }
         }
     }
 }
+
+/// Returns the next position on the path from `pos` to `target`, if any.
+fn step_towards(map: &Map, pos: Position, target: Position) -> Option<Position> {
+    let path = a_star_search(
+        map.xy_idx(pos.x, pos.y),
+        map.xy_idx(target.x, target.y),
+        map,
+    );
+    if path.success && path.steps.len() > 1 {
+        Some(Position {
+            x: path.steps[1] as u32 % map.width,
+            y: path.steps[1] as u32 / map.width,
+        })
+    } else {
+        None
+    }
+}
+
+/// Returns a random free position next to `pos`, if the random direction is not blocked.
+fn random_step(
+    map: &Map,
+    pos: Position,
+    player_pos: Position,
+    rng: &mut impl Rng,
+) -> Option<Position> {
+    let x = pos.x as i32 + rng.gen_range(-1..=1);
+    let y = pos.y as i32 + rng.gen_range(-1..=1);
+    let destination = Position {
+        x: x as u32,
+        y: y as u32,
+    };
+    // The player does not block tiles.
+    if (x, y) != (pos.x as i32, pos.y as i32)
+        && map.is_exit_valid(x, y)
+        && destination != player_pos
+    {
+        Some(destination)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::{StatusEffect, NORMAL_SPEED};
+    use crate::systems::energy::energy_system;
+
+    /// Spawns a mob at the start of row `y` that chases towards its end.
+    fn spawn_mob(world: &mut World, y: u32, effect: Option<StatusEffectKind>) -> Entity {
+        let mut status_effects = StatusEffects::default();
+        if let Some(kind) = effect {
+            status_effects.add(StatusEffect { kind, turns: 10 });
+        }
+        let mut movement = Movement::new(MovementMode::Chase);
+        movement.last_known_player_position = Some(Position { x: 18, y });
+        world
+            .spawn()
+            .insert_bundle((
+                Mob,
+                Position { x: 1, y },
+                Viewshed::new(1),
+                movement,
+                Energy {
+                    energy: 0,
+                    speed: NORMAL_SPEED,
+                },
+                status_effects,
+                EntityName {
+                    name: "Goblin".to_string(),
+                },
+            ))
+            .id()
+    }
+
+    #[test]
+    fn haste_and_slow_change_the_number_of_actions() {
+        let mut world = World::new();
+        world.insert_resource(Map::new(20, 10, 1));
+        world.insert_resource(GameRng::new(0));
+        world
+            .spawn()
+            .insert_bundle((Player, Position { x: 1, y: 8 }));
+        let normal = spawn_mob(&mut world, 2, None);
+        let hasted = spawn_mob(&mut world, 4, Some(StatusEffectKind::Haste));
+        let slowed = spawn_mob(&mut world, 6, Some(StatusEffectKind::Slow));
+
+        let mut stage = SystemStage::single_threaded();
+        stage.add_system(energy_system.label("energy"));
+        stage.add_system(monster_ai_system.after("energy"));
+        for _ in 0..2 {
+            stage.run(&mut world);
+        }
+
+        let steps = |entity| world.get::<Position>(entity).unwrap().x - 1;
+        assert_eq!(steps(normal), 2);
+        assert_eq!(steps(hasted), 3);
+        assert_eq!(steps(slowed), 1);
+    }
+}
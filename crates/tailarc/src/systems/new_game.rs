@@ -0,0 +1,105 @@
+//! Starting a new game.
+
+use bevy_ecs::prelude::*;
+use bracket_lib::prelude::*;
+
+use crate::components::{
+    Attributes, CanSufferDamage, CombatStats, Energy, EntityName, Player, PlayerBundle, Renderable,
+    Viewshed, NORMAL_SPEED,
+};
+use crate::gamelog::GameLog;
+use crate::map::{LevelStore, Map, MAP_HEIGHT, MAP_WIDTH};
+use crate::map_builders::level_builder;
+use crate::morgue::KillCounts;
+use crate::rng::GameRng;
+use crate::save::{ActiveSlot, Autosave, Run, SaveSlots};
+use crate::{RunState, TurnCounter, DEBUG_GOD_MODE};
+
+/// Starts a new game from the seed of the [`GameRng`].
+///
+/// All the entities of the previous game are despawned and the resources describing the game are
+/// reset.
+pub fn new_game_system(
+    mut commands: Commands,
+    mut state: ResMut<State<RunState>>,
+    mut map: ResMut<Map>,
+    mut level_store: ResMut<LevelStore>,
+    mut active_slot: ResMut<ActiveSlot>,
+    mut autosave: ResMut<Autosave>,
+    mut kill_counts: ResMut<KillCounts>,
+    mut turn_counter: ResMut<TurnCounter>,
+    game_log: Res<GameLog>,
+    run: Res<Run>,
+    rng: Res<GameRng>,
+    entities: Query<Entity>,
+) {
+    tracing::info!("Starting new game with seed {}", rng.seed());
+
+    for entity in entities.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    // Generate map.
+    let mut builder = level_builder(MAP_WIDTH, MAP_HEIGHT, 1, rng.seed());
+
+    *map = builder.build_map();
+    *level_store = LevelStore::default();
+    let starting_position = builder.starting_position();
+
+    // Spawn monsters.
+    builder.spawn_entities(&mut commands);
+
+    // Spawn player.
+    // The player is a bit better than an average monster.
+    let attributes = Attributes {
+        might: 12,
+        fitness: 12,
+        quickness: 12,
+        intelligence: 10,
+    };
+    let combat_stats = if DEBUG_GOD_MODE {
+        CombatStats {
+            hp: 1000,
+            max_hp: 1000,
+            defense: 1000,
+            power: 1000,
+        }
+    } else {
+        CombatStats {
+            hp: 100,
+            max_hp: 100,
+            defense: 2,
+            power: 5,
+        }
+    };
+    commands.spawn_bundle(PlayerBundle {
+        player: Player,
+        name: EntityName {
+            name: "Player".to_string(),
+        },
+        position: starting_position,
+        renderable: Renderable {
+            glyph: '@' as u16,
+            fg: RGB::named(YELLOW),
+            bg: RGB::named(BLACK),
+            z_index: 3,
+        },
+        viewshed: Viewshed::new(8),
+        combat_stats: combat_stats.with_attributes(&attributes),
+        attributes,
+        energy: Energy::new(NORMAL_SPEED),
+        can_suffer_damage: CanSufferDamage::default(),
+    });
+
+    // Reset the state of the previous game.
+    // Autosave into the first free slot, if any.
+    active_slot.0 = SaveSlots::read().first_empty();
+    *autosave = Autosave::from_env();
+    // A permadeath game is only saved when quitting.
+    autosave.enabled = !run.permadeath;
+    *kill_counts = KillCounts::default();
+    *turn_counter = TurnCounter::default();
+    *game_log.entries.lock().unwrap() = vec!["Welcome to Tailarc!".to_string()];
+
+    state.set(RunState::AwaitingInput).unwrap();
+}
@@ -0,0 +1,92 @@
+//! Reloading the raws while the game is running.
+
+use bevy_ecs::prelude::*;
+use bracket_lib::prelude::*;
+
+use crate::components::{
+    CombatStats, Energy, EntityName, Item, Mob, Movement, Ranged, Viewshed, NORMAL_SPEED,
+};
+use crate::gamelog::GameLog;
+use crate::raws::{get_item, get_mob, load_raws, RawsDir};
+
+/// Reloads the raws from disk when F5 is pressed. Only available in debug builds, for balancing.
+///
+/// With Shift, the mobs and items that are already spawned are also refreshed from their new
+/// definitions, matched by [`EntityName`]. Components that are no longer in a definition are kept.
+/// The hp of mobs are kept, within their new max hp, and so are their energy and what they
+/// remember of the player.
+pub fn reload_raws_system(
+    mut commands: Commands,
+    bterm: Res<BTerm>,
+    raws_dir: Res<RawsDir>,
+    game_log: Res<GameLog>,
+    mut mobs: Query<(Entity, &EntityName, &CombatStats, &Energy, &mut Movement), With<Mob>>,
+    items: Query<(Entity, &EntityName), With<Item>>,
+) {
+    if !cfg!(debug_assertions) || bterm.key != Some(VirtualKeyCode::F5) {
+        return;
+    }
+
+    if let Err(errors) = load_raws(raws_dir.0.as_deref()) {
+        for error in &errors {
+            tracing::error!("{}", error);
+        }
+        game_log.add_entry(format!(
+            "Could not reload raws: {} error(s), see the logs",
+            errors.len()
+        ));
+        return;
+    }
+    if !bterm.shift {
+        game_log.add_entry("Raws reloaded");
+        return;
+    }
+
+    let mut refreshed = 0;
+    for (entity, name, stats, energy, mut movement) in mobs.iter_mut() {
+        let mob = match get_mob(&name.name) {
+            Some(mob) => mob,
+            None => continue,
+        };
+        let new_stats = mob.stats.with_attributes(&mob.attributes);
+        commands.entity(entity).insert_bundle((
+            mob.renderable,
+            Viewshed::new(mob.vision_range),
+            mob.attributes,
+            CombatStats {
+                hp: stats.hp.min(new_stats.max_hp),
+                ..new_stats
+            },
+            Energy {
+                speed: mob.speed.unwrap_or(NORMAL_SPEED),
+                ..*energy
+            },
+        ));
+        movement.mode = mob.movement;
+        refreshed += 1;
+    }
+    for (entity, name) in items.iter() {
+        let item = match get_item(&name.name) {
+            Some(item) => item,
+            None => continue,
+        };
+        let mut e = commands.entity(entity);
+        if let Some(renderable) = item.renderable {
+            e.insert(renderable);
+        }
+        if let Some(consumable) = item.consumable {
+            e.insert(consumable.effects);
+        }
+        if let Some(range) = item.ranged {
+            e.insert(Ranged { range });
+        }
+        if let Some(equippable) = item.equippable {
+            e.insert(equippable);
+        }
+        if let Some(stats) = item.stats {
+            e.insert(stats);
+        }
+        refreshed += 1;
+    }
+    game_log.add_entry(format!("Raws reloaded, {} entities refreshed", refreshed));
+}
@@ -1,32 +1,123 @@
 //! Game saving systems.
 
-use std::fs;
-use std::path::{Path, PathBuf};
+use std::io;
 
 use bevy_ecs::prelude::*;
 use bevy_reflect::TypeRegistryArc;
 use bevy_scene::serde::SceneSerializer;
 use bevy_scene::DynamicScene;
-use directories::ProjectDirs;
 
-use crate::components::register_component_types;
+use crate::components::{register_component_types, CombatStats, EntityName, Player};
+use crate::gamelog::GameLog;
+use crate::map::{LevelStore, Map};
+use crate::morgue::KillCounts;
+use crate::rng::GameRng;
+use crate::save::{
+    timestamp_now, write_slot, ActiveSlot, Autosave, Run, SaveData, SaveMetadata, SAVE_VERSION,
+};
+use crate::{RunState, TurnCounter};
+
+/// Saves the game into the given slot.
+pub fn save_game(world: &mut World, slot: usize) -> io::Result<()> {
+    let (name, stats) = world
+        .query_filtered::<(&EntityName, &CombatStats), With<Player>>()
+        .iter(world)
+        .next()
+        .expect("player should exist");
+    let metadata = SaveMetadata {
+        character_name: name.name.clone(),
+        depth: world.get_resource::<Map>().unwrap().depth,
+        hp: stats.hp,
+        max_hp: stats.max_hp,
+        turn: world.get_resource::<TurnCounter>().unwrap().turn,
+        timestamp: timestamp_now(),
+        run_id: world.get_resource::<Run>().unwrap().id,
+        permadeath: world.get_resource::<Run>().unwrap().permadeath,
+    };
+    let last_save = (metadata.turn, metadata.depth);
 
-pub fn save_game_system(world: &mut World) {
     let type_registry = world.get_resource::<TypeRegistryArc>().unwrap();
     register_component_types(&mut type_registry.write());
 
     let scene = DynamicScene::from_world(world, type_registry);
-    let serializer = SceneSerializer::new(&scene, type_registry);
+    let save_data = SaveData {
+        version: SAVE_VERSION,
+        metadata,
+        seed: world.get_resource::<GameRng>().unwrap().seed(),
+        map: world.get_resource::<Map>().unwrap(),
+        levels: &world.get_resource::<LevelStore>().unwrap().levels,
+        game_log: world
+            .get_resource::<GameLog>()
+            .unwrap()
+            .entries
+            .lock()
+            .unwrap()
+            .clone(),
+        kills: &world.get_resource::<KillCounts>().unwrap().kills,
+        scene: SceneSerializer::new(&scene, type_registry),
+    };
+
+    let data = serde_json::to_string(&save_data).expect("could not serialize save data into JSON");
+    write_slot(slot, &data)?;
+
+    world.get_resource_mut::<Autosave>().unwrap().last_save = Some(last_save);
+    Ok(())
+}
+
+/// Saves the game into the [`ActiveSlot`].
+///
+/// In [`RunState::SaveGame`], the game continues after saving. In [`RunState::SaveAndQuit`], the
+/// game exits.
+pub fn save_game_system(world: &mut World) {
+    let slot = world
+        .get_resource::<ActiveSlot>()
+        .unwrap()
+        .0
+        .expect("no save slot selected");
+    let quit = *world.get_resource::<State<RunState>>().unwrap().current() == RunState::SaveAndQuit;
+
+    let result = save_game(world, slot);
+    let game_log = world.get_resource::<GameLog>().unwrap();
+    match result {
+        Ok(()) if quit => std::process::exit(0),
+        Ok(()) => game_log.add_entry(format!("Game saved in slot {}", slot + 1)),
+        Err(err) => {
+            tracing::error!("could not write save data: {}", err);
+            game_log.add_entry("Could not save the game!");
+        }
+    }
+
+    let mut state = world.get_resource_mut::<State<RunState>>().unwrap();
+    state.set(RunState::AwaitingInput).unwrap();
+}
 
-    let data = serde_json::to_string(&serializer).expect("could not serialize scene into JSON");
+/// Autosaves the game into the [`ActiveSlot`] when [`Autosave::is_due`].
+pub fn autosave_system(world: &mut World) {
+    let slot = match world.get_resource::<ActiveSlot>().unwrap().0 {
+        Some(slot) => slot,
+        None => return,
+    };
+    if !world.get_resource::<Autosave>().unwrap().enabled {
+        return;
+    }
+    let turn = world.get_resource::<TurnCounter>().unwrap().turn;
+    let depth = world.get_resource::<Map>().unwrap().depth;
 
-    let project_dirs = ProjectDirs::from("", "", "Tailarc").expect("could not create ProjectDirs");
-    let data_dir = project_dirs.data_dir();
+    let mut autosave = world.get_resource_mut::<Autosave>().unwrap();
+    if autosave.last_save.is_none() {
+        autosave.last_save = Some((turn, depth));
+    }
+    if !autosave.is_due(turn, depth) {
+        return;
+    }
 
-    fs::create_dir_all(data_dir).expect("could not create data directory");
-    let data_path = [data_dir, Path::new("save.json")]
-        .into_iter()
-        .collect::<PathBuf>();
-    fs::write(data_path, data).expect("could not write save data");
-    std::process::exit(0);
+    if let Err(err) = save_game(world, slot) {
+        tracing::error!("could not autosave: {}", err);
+        world
+            .get_resource::<GameLog>()
+            .unwrap()
+            .add_entry("Could not autosave the game!");
+        // Do not retry every tick.
+        world.get_resource_mut::<Autosave>().unwrap().last_save = Some((turn, depth));
+    }
 }
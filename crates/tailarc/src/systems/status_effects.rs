@@ -0,0 +1,53 @@
+//! Status effects.
+
+use bevy_ecs::prelude::*;
+
+use crate::components::{CanSufferDamage, Player, Position, StatusEffectKind, StatusEffects};
+use crate::gamelog::GameLog;
+
+/// Ticks the [`StatusEffects`] of the entities on the current level once per turn.
+///
+/// Poison and regeneration are resolved through [`CanSufferDamage`]. Effects are removed when
+/// their turns run out, and the component is removed when no effect is left.
+pub fn status_effect_system(
+    mut commands: Commands,
+    game_log: Res<GameLog>,
+    mut q: Query<
+        (
+            Entity,
+            &mut StatusEffects,
+            Option<&mut CanSufferDamage>,
+            Option<&Player>,
+        ),
+        With<Position>,
+    >,
+) {
+    for (entity, mut status_effects, mut can_suffer_damage, player) in q.iter_mut() {
+        for effect in status_effects.effects.iter_mut() {
+            match (effect.kind, can_suffer_damage.as_mut()) {
+                (StatusEffectKind::Poison { damage }, Some(can_suffer_damage)) => {
+                    can_suffer_damage.amount.push(damage);
+                    can_suffer_damage.last_attacker = Some("Poison".to_string());
+                    if player.is_some() {
+                        game_log.add_entry(format!("You suffer {} poison damage", damage));
+                    }
+                }
+                // Negative damage heals. The hp cannot go over the maximum.
+                (StatusEffectKind::Regeneration { heal }, Some(can_suffer_damage)) => {
+                    can_suffer_damage.amount.push(-heal);
+                }
+                _ => {}
+            }
+
+            effect.turns -= 1;
+            if effect.turns <= 0 && player.is_some() {
+                game_log.add_entry(format!("{} wears off", effect.kind.name()));
+            }
+        }
+
+        status_effects.effects.retain(|effect| effect.turns > 0);
+        if status_effects.effects.is_empty() {
+            commands.entity(entity).remove::<StatusEffects>();
+        }
+    }
+}
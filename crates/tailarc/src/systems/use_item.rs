@@ -1,17 +1,55 @@
+use std::time::Duration;
+
 use bevy_ecs::prelude::*;
+use bracket_lib::prelude::*;
+use rand::seq::SliceRandom;
 
 use crate::components::{
-    CombatStats, ConsumableEffects, EntityName, Equippable, Equipped, Item, Owned, Player,
-    WantsToUseItem,
+    CanSufferDamage, CombatStats, ConsumableEffects, Energy, EntityName, Equippable, Equipped,
+    Item, Owned, Player, Position, Renderable, StatusEffects, Viewshed, WantsToUseItem,
+    ACTION_COST, EQUIP_COST,
 };
 use crate::gamelog::GameLog;
+use crate::map::{Map, Tile};
+use crate::rng::GameRng;
+
+use super::action::{check_owner, ActionRejection};
+use super::particle::ParticleBuilder;
+
+/// Returns the renderable of a particle showing an item effect.
+fn effect_particle(glyph: char, color: (u8, u8, u8)) -> Renderable {
+    Renderable {
+        glyph: to_cp437(glyph),
+        fg: RGB::named(color),
+        bg: RGB::named(BLACK),
+        z_index: 4, // Particles should always be on the very top.
+    }
+}
 
 /// Processes all the [`WantsToUseItem`] components and removes them from the entities.
+///
+/// Consumables affect the entities on their target tile if they have one (see
+/// [`Ranged`](crate::components::Ranged)), or the entity using them otherwise. Items with an area
+/// of effect also affect the entities on the tiles around the target that can be seen from it.
+///
+/// Items that cannot be used are rejected with an [`ActionRejection`].
 pub fn use_item_system(
     mut commands: Commands,
     game_log: Res<GameLog>,
-    mut wants_use: Query<(Entity, &WantsToUseItem, Option<&mut CombatStats>)>,
-    owned: Query<&Owned>,
+    map: Res<Map>,
+    mut rng: ResMut<GameRng>,
+    mut particle_builder: ResMut<ParticleBuilder>,
+    wants_use: Query<(Entity, &WantsToUseItem)>,
+    mut targets: Query<(
+        &EntityName,
+        &mut Position,
+        Option<&mut CombatStats>,
+        Option<&mut CanSufferDamage>,
+        Option<&mut Viewshed>,
+        Option<&mut StatusEffects>,
+    )>,
+    items: Query<Option<&Owned>, With<Item>>,
+    mut energy: Query<&mut Energy>,
     consumables: Query<(Entity, &ConsumableEffects, &EntityName), With<Item>>,
     equippables: Query<(Entity, &Equippable, &EntityName), With<Item>>,
     equipped: Query<(Entity, &Equipped, &EntityName)>,
@@ -19,32 +57,165 @@ pub fn use_item_system(
 ) {
     let player_entity = player.single();
 
-    for (entity, wants_use, stats) in wants_use.iter_mut() {
-        let owned = owned
-            .get(wants_use.item)
-            .expect("cannot use an item that is not owned");
+    for (entity, wants_use) in wants_use.iter() {
+        // Remove WantsToUseItem component from entity to prevent using the item twice.
+        commands.entity(entity).remove::<WantsToUseItem>();
 
-        // Make sure that owned matches with the entity that wants to use it.
-        if owned.owner != entity {
-            panic!("cannot use an item that you do not own");
-        }
+        // Make sure that the item is owned by the entity that wants to use it, and that the entity
+        // is on the map to aim it.
+        let checked = items
+            .get(wants_use.item)
+            .map_err(|_| ActionRejection::NotAnItem)
+            .and_then(|owned| check_owner(entity, owned))
+            .and_then(|()| {
+                targets
+                    .get(entity)
+                    .map(|(name, &pos, ..)| (name.name.clone(), pos))
+                    .map_err(|_| ActionRejection::NotOnMap)
+            });
+        let (user_name, user_pos) = match checked {
+            Ok(user) => user,
+            Err(rejection) => {
+                if entity == player_entity {
+                    game_log.add_entry(rejection.to_string());
+                }
+                continue;
+            }
+        };
 
         // Consumable - apply the effect of the item
         if let Ok((item, effect, name)) = consumables.get(wants_use.item) {
-            if let Some(mut stats) = stats {
-                if entity == player_entity {
-                    // If it is the player that is using the item, display message in game log.
-                    game_log.add_entry(format!("You use {}", name.name));
+            if entity == player_entity {
+                // If it is the player that is using the item, display message in game log.
+                game_log.add_entry(format!("You use {}", name.name));
+            }
+
+            // Find the tiles affected by the item.
+            let center = wants_use.target.unwrap_or(user_pos);
+            let tiles: Vec<Position> = match effect.area_of_effect {
+                Some(radius) => field_of_view(Point::new(center.x, center.y), radius, &*map)
+                    .into_iter()
+                    .filter(|p| {
+                        p.x >= 0 && p.x < map.width as i32 && p.y >= 0 && p.y < map.height as i32
+                    })
+                    .map(|p| Position {
+                        x: p.x as u32,
+                        y: p.y as u32,
+                    })
+                    .collect(),
+                None => vec![center],
+            };
+
+            for tile in tiles {
+                if effect.area_of_effect.is_some() {
+                    particle_builder.request(
+                        tile,
+                        effect_particle('░', ORANGE),
+                        Duration::from_millis(200),
+                    );
                 }
 
-                if let Some(heal) = effect.heal {
-                    stats.hp = i32::min(stats.max_hp, stats.hp + heal);
+                for &target in &map.tile_content[map.xy_idx(tile.x, tile.y)] {
+                    // Entities without a CombatStats component are not affected.
+                    let (
+                        target_name,
+                        mut pos,
+                        mut stats,
+                        can_suffer_damage,
+                        viewshed,
+                        status_effects,
+                    ) = match targets.get_mut(target) {
+                        Ok((
+                            name,
+                            pos,
+                            Some(stats),
+                            can_suffer_damage,
+                            viewshed,
+                            status_effects,
+                        )) => (
+                            name,
+                            pos,
+                            stats,
+                            can_suffer_damage,
+                            viewshed,
+                            status_effects,
+                        ),
+                        _ => continue,
+                    };
+
+                    if let Some(heal) = effect.heal {
+                        stats.hp = i32::min(stats.max_hp, stats.hp + heal);
+                        particle_builder.request(
+                            *pos,
+                            effect_particle('♥', GREEN),
+                            Duration::from_millis(200),
+                        );
+                    }
+
+                    if let (Some(damage), Some(mut can_suffer_damage)) =
+                        (effect.damage, can_suffer_damage)
+                    {
+                        can_suffer_damage.amount.push(damage);
+                        can_suffer_damage.last_attacker = Some(user_name.clone());
+                        game_log.add_entry(format!(
+                            "{} hits {} for {} hp",
+                            name.name, target_name.name, damage
+                        ));
+                        particle_builder.request(
+                            *pos,
+                            effect_particle('‼', RED),
+                            Duration::from_millis(200),
+                        );
+                    }
+
+                    if let Some(status_effect) = effect.status_effect {
+                        match status_effects {
+                            Some(mut status_effects) => status_effects.add(status_effect),
+                            None => {
+                                commands.entity(target).insert(StatusEffects {
+                                    effects: vec![status_effect],
+                                });
+                            }
+                        }
+                        game_log.add_entry(format!(
+                            "{} is affected by {}",
+                            target_name.name,
+                            status_effect.kind.name().to_lowercase()
+                        ));
+                        particle_builder.request(
+                            *pos,
+                            effect_particle('?', MAGENTA),
+                            Duration::from_millis(200),
+                        );
+                    }
+
+                    if effect.teleport {
+                        let free_tiles: Vec<usize> = (0..map.tiles.len())
+                            .filter(|&idx| map.tiles[idx] == Tile::Floor && !map.blocked[idx])
+                            .collect();
+                        if let Some(&idx) = free_tiles.choose(&mut *rng) {
+                            particle_builder.request(
+                                *pos,
+                                effect_particle('*', CYAN),
+                                Duration::from_millis(200),
+                            );
+                            *pos = Position {
+                                x: idx as u32 % map.width,
+                                y: idx as u32 / map.width,
+                            };
+                            if let Some(mut viewshed) = viewshed {
+                                viewshed.dirty = true;
+                            }
+                            game_log.add_entry(format!("{} is teleported", target_name.name));
+                        }
+                    }
                 }
-            } else {
-                // Using an item without a CombatStats component does nothing.
             }
             // Despawn the item since it has been used.
             commands.entity(item).despawn();
+            if let Ok(mut energy) = energy.get_mut(entity) {
+                energy.energy -= ACTION_COST;
+            }
         } else if let Ok((item, equippable, name)) = equippables.get(wants_use.item) {
             // If another item is already equipped in the slot, remove it.
             for (equipped_entity, already_equipped, already_equipped_name) in equipped.iter() {
@@ -74,11 +245,107 @@ pub fn use_item_system(
                 slot: equippable.slot,
             });
             commands.entity(item).remove::<Owned>();
-        } else {
-            game_log.add_entry("You cannot use that item");
+            // Equipping takes longer than using an item.
+            if let Ok(mut energy) = energy.get_mut(entity) {
+                energy.energy -= EQUIP_COST;
+            }
+        } else if entity == player_entity {
+            game_log.add_entry(ActionRejection::NotUsable.to_string());
         }
+    }
+}
 
-        // Remove WantsToUseItem component from entity to prevent using the item twice.
-        commands.entity(entity).remove::<WantsToUseItem>();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::action::test_utils::{
+        assert_rejected, run_system, spawn_potion, world_with_player,
+    };
+
+    #[test]
+    fn use_destroyed_item() {
+        let (mut world, player) = world_with_player();
+        let potion = spawn_potion(&mut world, Some(Owned { owner: player }));
+        world.despawn(potion);
+        world.entity_mut(player).insert(WantsToUseItem {
+            item: potion,
+            target: None,
+        });
+
+        run_system(&mut world, use_item_system);
+        assert_rejected(&world, player, ActionRejection::NotAnItem);
+    }
+
+    #[test]
+    fn use_something_that_is_not_an_item() {
+        let (mut world, player) = world_with_player();
+        let wall = world.spawn().insert(Position { x: 2, y: 1 }).id();
+        world.entity_mut(player).insert(WantsToUseItem {
+            item: wall,
+            target: None,
+        });
+
+        run_system(&mut world, use_item_system);
+        assert_rejected(&world, player, ActionRejection::NotAnItem);
+    }
+
+    #[test]
+    fn use_item_owned_by_someone_else() {
+        let (mut world, player) = world_with_player();
+        let other = world.spawn().id();
+        let potion = spawn_potion(&mut world, Some(Owned { owner: other }));
+        world.entity_mut(player).insert(WantsToUseItem {
+            item: potion,
+            target: None,
+        });
+
+        run_system(&mut world, use_item_system);
+        assert_rejected(&world, player, ActionRejection::NotOwned);
+        assert!(world.get_entity(potion).is_some());
+    }
+
+    #[test]
+    fn use_item_that_is_not_usable() {
+        let (mut world, player) = world_with_player();
+        let rock = world
+            .spawn()
+            .insert_bundle((Item, Owned { owner: player }))
+            .id();
+        world.entity_mut(player).insert(WantsToUseItem {
+            item: rock,
+            target: None,
+        });
+
+        run_system(&mut world, use_item_system);
+        assert_rejected(&world, player, ActionRejection::NotUsable);
+    }
+
+    #[test]
+    fn use_item_off_the_map() {
+        let (mut world, player) = world_with_player();
+        world.entity_mut(player).remove::<Position>();
+        let potion = spawn_potion(&mut world, Some(Owned { owner: player }));
+        world.entity_mut(player).insert(WantsToUseItem {
+            item: potion,
+            target: None,
+        });
+
+        run_system(&mut world, use_item_system);
+        assert_rejected(&world, player, ActionRejection::NotOnMap);
+        assert!(world.get_entity(potion).is_some());
+    }
+
+    #[test]
+    fn use_owned_item() {
+        let (mut world, player) = world_with_player();
+        let potion = spawn_potion(&mut world, Some(Owned { owner: player }));
+        world.entity_mut(player).insert(WantsToUseItem {
+            item: potion,
+            target: None,
+        });
+
+        run_system(&mut world, use_item_system);
+        assert!(world.get_entity(potion).is_none());
+        assert_eq!(world.get::<Energy>(player).unwrap().energy, 0);
     }
 }
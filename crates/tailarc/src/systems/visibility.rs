@@ -10,18 +10,7 @@ pub fn visibility_system(
 ) {
     for (mut viewshed, pos, player) in q.iter_mut() {
         if viewshed.dirty {
-            viewshed.visible_tiles.clear();
-            viewshed.visible_tiles =
-                field_of_view_set(Point::new(pos.x, pos.y), viewshed.range, &*map)
-                    .into_iter()
-                    .map(|p| Position {
-                        x: p.x as u32,
-                        y: p.y as u32,
-                    })
-                    .collect();
-            viewshed
-                .visible_tiles
-                .retain(|p| p.x < map.width && p.y < map.height);
+            update_viewshed(&mut viewshed, *pos, &map);
 
             // Reveal what the player can see.
             if player.is_some() {
@@ -32,8 +21,21 @@ pub fn visibility_system(
                     map.visible_tiles[idx] = true;
                 }
             }
-
-            viewshed.dirty = false;
         }
     }
 }
+
+/// Recomputes the tiles visible from `pos` and marks the viewshed as up to date.
+pub fn update_viewshed(viewshed: &mut Viewshed, pos: Position, map: &Map) {
+    viewshed.visible_tiles = field_of_view_set(Point::new(pos.x, pos.y), viewshed.range, map)
+        .into_iter()
+        .map(|p| Position {
+            x: p.x as u32,
+            y: p.y as u32,
+        })
+        .collect();
+    viewshed
+        .visible_tiles
+        .retain(|p| p.x < map.width && p.y < map.height);
+    viewshed.dirty = false;
+}